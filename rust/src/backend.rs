@@ -0,0 +1,823 @@
+// The node operations the library uses, behind a trait so the flow doesn't depend on the
+// concrete client.
+
+use crate::rpc::batch_get_raw_transactions;
+use bitcoincore_rpc::bitcoin::address::NetworkUnchecked;
+use bitcoincore_rpc::bitcoin::{Address, Amount, Block, BlockHash, Transaction, Txid};
+use bitcoincore_rpc::{json, Client, RawTx, Result, RpcApi};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+
+// Every call the library makes to a node, with the signatures of the `RpcApi` methods of the
+// same names, so the flow can run against something other than `Client`, which implements it
+// by passing each call on. Functions that need nothing else from the client take
+// `&impl RpcBackend`, and the flow takes `Wallets` of any backend.
+pub trait RpcBackend {
+    // Any RPC, for the ones without a typed method here (see rpc::rpc_call).
+    fn call<T: DeserializeOwned>(&self, method: &str, args: &[Value]) -> Result<T>;
+
+    // Chain and blocks.
+    fn get_blockchain_info(&self) -> Result<json::GetBlockchainInfoResult>;
+    fn get_block_count(&self) -> Result<u64>;
+    fn get_block_hash(&self, height: u64) -> Result<BlockHash>;
+    fn get_block(&self, hash: &BlockHash) -> Result<Block>;
+    fn get_block_info(&self, hash: &BlockHash) -> Result<json::GetBlockResult>;
+    fn get_raw_transaction(
+        &self,
+        txid: &Txid,
+        block_hash: Option<&BlockHash>,
+    ) -> Result<Transaction>;
+    // `getrawtransaction` for each of `txids` in one JSON-RPC batch, which `RpcApi` has no
+    // method for (see rpc::batch_get_raw_transactions).
+    fn get_raw_transactions(&self, txids: &[Txid]) -> Result<Vec<Transaction>>;
+    fn decode_raw_transaction<R: RawTx>(
+        &self,
+        tx: R,
+        is_witness: Option<bool>,
+    ) -> Result<json::DecodeRawTransactionResult>;
+    fn get_tx_out(
+        &self,
+        txid: &Txid,
+        vout: u32,
+        include_mempool: Option<bool>,
+    ) -> Result<Option<json::GetTxOutResult>>;
+
+    // Mempool and relay.
+    fn get_raw_mempool(&self) -> Result<Vec<Txid>>;
+    fn get_mempool_entry(&self, txid: &Txid) -> Result<json::GetMempoolEntryResult>;
+    fn estimate_smart_fee(
+        &self,
+        conf_target: u16,
+        estimate_mode: Option<json::EstimateMode>,
+    ) -> Result<json::EstimateSmartFeeResult>;
+    fn send_raw_transaction<R: RawTx>(&self, tx: R) -> Result<Txid>;
+
+    // Wallets.
+    fn list_wallets(&self) -> Result<Vec<String>>;
+    fn list_wallet_dir(&self) -> Result<Vec<String>>;
+    fn create_wallet(
+        &self,
+        wallet: &str,
+        disable_private_keys: Option<bool>,
+        blank: Option<bool>,
+        passphrase: Option<&str>,
+        avoid_reuse: Option<bool>,
+    ) -> Result<json::LoadWalletResult>;
+    fn load_wallet(&self, wallet: &str) -> Result<json::LoadWalletResult>;
+    fn get_wallet_info(&self) -> Result<json::GetWalletInfoResult>;
+
+    // Addresses, balances and wallet transactions.
+    fn get_new_address(
+        &self,
+        label: Option<&str>,
+        address_type: Option<json::AddressType>,
+    ) -> Result<Address<NetworkUnchecked>>;
+    fn get_address_info(&self, address: &Address) -> Result<json::GetAddressInfoResult>;
+    fn get_balance(
+        &self,
+        minconf: Option<usize>,
+        include_watchonly: Option<bool>,
+    ) -> Result<Amount>;
+    fn get_balances(&self) -> Result<json::GetBalancesResult>;
+    fn list_unspent(
+        &self,
+        minconf: Option<usize>,
+        maxconf: Option<usize>,
+        addresses: Option<&[&Address]>,
+        include_unsafe: Option<bool>,
+        query_options: Option<json::ListUnspentQueryOptions>,
+    ) -> Result<Vec<json::ListUnspentResultEntry>>;
+    fn get_transaction(
+        &self,
+        txid: &Txid,
+        include_watchonly: Option<bool>,
+    ) -> Result<json::GetTransactionResult>;
+    fn list_transactions(
+        &self,
+        label: Option<&str>,
+        count: Option<usize>,
+        skip: Option<usize>,
+        include_watchonly: Option<bool>,
+    ) -> Result<Vec<json::ListTransactionResult>>;
+    fn generate_to_address(&self, block_num: u64, address: &Address) -> Result<Vec<BlockHash>>;
+
+    // Building and signing transactions.
+    fn create_raw_transaction_hex(
+        &self,
+        utxos: &[json::CreateRawTransactionInput],
+        outs: &HashMap<String, Amount>,
+        locktime: Option<i64>,
+        replaceable: Option<bool>,
+    ) -> Result<String>;
+    fn fund_raw_transaction<R: RawTx>(
+        &self,
+        tx: R,
+        options: Option<&json::FundRawTransactionOptions>,
+        is_witness: Option<bool>,
+    ) -> Result<json::FundRawTransactionResult>;
+    fn sign_raw_transaction_with_wallet<R: RawTx>(
+        &self,
+        tx: R,
+        utxos: Option<&[json::SignRawTransactionInput]>,
+        sighash_type: Option<json::SigHashType>,
+    ) -> Result<json::SignRawTransactionResult>;
+    fn wallet_create_funded_psbt(
+        &self,
+        inputs: &[json::CreateRawTransactionInput],
+        outputs: &HashMap<String, Amount>,
+        locktime: Option<i64>,
+        options: Option<json::WalletCreateFundedPsbtOptions>,
+        bip32derivs: Option<bool>,
+    ) -> Result<json::WalletCreateFundedPsbtResult>;
+    fn wallet_process_psbt(
+        &self,
+        psbt: &str,
+        sign: Option<bool>,
+        sighash_type: Option<json::SigHashType>,
+        bip32derivs: Option<bool>,
+    ) -> Result<json::WalletProcessPsbtResult>;
+    fn finalize_psbt(&self, psbt: &str, extract: Option<bool>) -> Result<json::FinalizePsbtResult>;
+}
+
+impl RpcBackend for Client {
+    fn call<T: DeserializeOwned>(&self, method: &str, args: &[Value]) -> Result<T> {
+        RpcApi::call(self, method, args)
+    }
+
+    fn get_blockchain_info(&self) -> Result<json::GetBlockchainInfoResult> {
+        RpcApi::get_blockchain_info(self)
+    }
+
+    fn get_block_count(&self) -> Result<u64> {
+        RpcApi::get_block_count(self)
+    }
+
+    fn get_block_hash(&self, height: u64) -> Result<BlockHash> {
+        RpcApi::get_block_hash(self, height)
+    }
+
+    fn get_block(&self, hash: &BlockHash) -> Result<Block> {
+        RpcApi::get_block(self, hash)
+    }
+
+    fn get_block_info(&self, hash: &BlockHash) -> Result<json::GetBlockResult> {
+        RpcApi::get_block_info(self, hash)
+    }
+
+    fn get_raw_transaction(
+        &self,
+        txid: &Txid,
+        block_hash: Option<&BlockHash>,
+    ) -> Result<Transaction> {
+        RpcApi::get_raw_transaction(self, txid, block_hash)
+    }
+
+    fn get_raw_transactions(&self, txids: &[Txid]) -> Result<Vec<Transaction>> {
+        batch_get_raw_transactions(self, txids)
+    }
+
+    fn decode_raw_transaction<R: RawTx>(
+        &self,
+        tx: R,
+        is_witness: Option<bool>,
+    ) -> Result<json::DecodeRawTransactionResult> {
+        RpcApi::decode_raw_transaction(self, tx, is_witness)
+    }
+
+    fn get_tx_out(
+        &self,
+        txid: &Txid,
+        vout: u32,
+        include_mempool: Option<bool>,
+    ) -> Result<Option<json::GetTxOutResult>> {
+        RpcApi::get_tx_out(self, txid, vout, include_mempool)
+    }
+
+    fn get_raw_mempool(&self) -> Result<Vec<Txid>> {
+        RpcApi::get_raw_mempool(self)
+    }
+
+    fn get_mempool_entry(&self, txid: &Txid) -> Result<json::GetMempoolEntryResult> {
+        RpcApi::get_mempool_entry(self, txid)
+    }
+
+    fn estimate_smart_fee(
+        &self,
+        conf_target: u16,
+        estimate_mode: Option<json::EstimateMode>,
+    ) -> Result<json::EstimateSmartFeeResult> {
+        RpcApi::estimate_smart_fee(self, conf_target, estimate_mode)
+    }
+
+    fn send_raw_transaction<R: RawTx>(&self, tx: R) -> Result<Txid> {
+        RpcApi::send_raw_transaction(self, tx)
+    }
+
+    fn list_wallets(&self) -> Result<Vec<String>> {
+        RpcApi::list_wallets(self)
+    }
+
+    fn list_wallet_dir(&self) -> Result<Vec<String>> {
+        RpcApi::list_wallet_dir(self)
+    }
+
+    fn create_wallet(
+        &self,
+        wallet: &str,
+        disable_private_keys: Option<bool>,
+        blank: Option<bool>,
+        passphrase: Option<&str>,
+        avoid_reuse: Option<bool>,
+    ) -> Result<json::LoadWalletResult> {
+        RpcApi::create_wallet(
+            self,
+            wallet,
+            disable_private_keys,
+            blank,
+            passphrase,
+            avoid_reuse,
+        )
+    }
+
+    fn load_wallet(&self, wallet: &str) -> Result<json::LoadWalletResult> {
+        RpcApi::load_wallet(self, wallet)
+    }
+
+    fn get_wallet_info(&self) -> Result<json::GetWalletInfoResult> {
+        RpcApi::get_wallet_info(self)
+    }
+
+    fn get_new_address(
+        &self,
+        label: Option<&str>,
+        address_type: Option<json::AddressType>,
+    ) -> Result<Address<NetworkUnchecked>> {
+        RpcApi::get_new_address(self, label, address_type)
+    }
+
+    fn get_address_info(&self, address: &Address) -> Result<json::GetAddressInfoResult> {
+        RpcApi::get_address_info(self, address)
+    }
+
+    fn get_balance(
+        &self,
+        minconf: Option<usize>,
+        include_watchonly: Option<bool>,
+    ) -> Result<Amount> {
+        RpcApi::get_balance(self, minconf, include_watchonly)
+    }
+
+    fn get_balances(&self) -> Result<json::GetBalancesResult> {
+        RpcApi::get_balances(self)
+    }
+
+    fn list_unspent(
+        &self,
+        minconf: Option<usize>,
+        maxconf: Option<usize>,
+        addresses: Option<&[&Address]>,
+        include_unsafe: Option<bool>,
+        query_options: Option<json::ListUnspentQueryOptions>,
+    ) -> Result<Vec<json::ListUnspentResultEntry>> {
+        RpcApi::list_unspent(
+            self,
+            minconf,
+            maxconf,
+            addresses,
+            include_unsafe,
+            query_options,
+        )
+    }
+
+    fn get_transaction(
+        &self,
+        txid: &Txid,
+        include_watchonly: Option<bool>,
+    ) -> Result<json::GetTransactionResult> {
+        RpcApi::get_transaction(self, txid, include_watchonly)
+    }
+
+    fn list_transactions(
+        &self,
+        label: Option<&str>,
+        count: Option<usize>,
+        skip: Option<usize>,
+        include_watchonly: Option<bool>,
+    ) -> Result<Vec<json::ListTransactionResult>> {
+        RpcApi::list_transactions(self, label, count, skip, include_watchonly)
+    }
+
+    fn generate_to_address(&self, block_num: u64, address: &Address) -> Result<Vec<BlockHash>> {
+        RpcApi::generate_to_address(self, block_num, address)
+    }
+
+    fn create_raw_transaction_hex(
+        &self,
+        utxos: &[json::CreateRawTransactionInput],
+        outs: &HashMap<String, Amount>,
+        locktime: Option<i64>,
+        replaceable: Option<bool>,
+    ) -> Result<String> {
+        RpcApi::create_raw_transaction_hex(self, utxos, outs, locktime, replaceable)
+    }
+
+    fn fund_raw_transaction<R: RawTx>(
+        &self,
+        tx: R,
+        options: Option<&json::FundRawTransactionOptions>,
+        is_witness: Option<bool>,
+    ) -> Result<json::FundRawTransactionResult> {
+        RpcApi::fund_raw_transaction(self, tx, options, is_witness)
+    }
+
+    fn sign_raw_transaction_with_wallet<R: RawTx>(
+        &self,
+        tx: R,
+        utxos: Option<&[json::SignRawTransactionInput]>,
+        sighash_type: Option<json::SigHashType>,
+    ) -> Result<json::SignRawTransactionResult> {
+        RpcApi::sign_raw_transaction_with_wallet(self, tx, utxos, sighash_type)
+    }
+
+    fn wallet_create_funded_psbt(
+        &self,
+        inputs: &[json::CreateRawTransactionInput],
+        outputs: &HashMap<String, Amount>,
+        locktime: Option<i64>,
+        options: Option<json::WalletCreateFundedPsbtOptions>,
+        bip32derivs: Option<bool>,
+    ) -> Result<json::WalletCreateFundedPsbtResult> {
+        RpcApi::wallet_create_funded_psbt(self, inputs, outputs, locktime, options, bip32derivs)
+    }
+
+    fn wallet_process_psbt(
+        &self,
+        psbt: &str,
+        sign: Option<bool>,
+        sighash_type: Option<json::SigHashType>,
+        bip32derivs: Option<bool>,
+    ) -> Result<json::WalletProcessPsbtResult> {
+        RpcApi::wallet_process_psbt(self, psbt, sign, sighash_type, bip32derivs)
+    }
+
+    fn finalize_psbt(&self, psbt: &str, extract: Option<bool>) -> Result<json::FinalizePsbtResult> {
+        RpcApi::finalize_psbt(self, psbt, extract)
+    }
+}
+
+// A stand-in node for unit tests: a wallet holding the transactions and addresses a test sets
+// up, with no bitcoind behind it. Calls it has no answer for fail like an RPC the node refused.
+#[cfg(test)]
+pub(crate) mod mock {
+    use super::RpcBackend;
+    use bitcoincore_rpc::bitcoin::address::NetworkUnchecked;
+    use bitcoincore_rpc::bitcoin::consensus::encode::{deserialize, serialize_hex};
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use bitcoincore_rpc::bitcoin::hex::{DisplayHex, FromHex};
+    use bitcoincore_rpc::bitcoin::{
+        Address, Amount, Block, BlockHash, Network, OutPoint, Transaction, TxOut, Txid,
+    };
+    use bitcoincore_rpc::jsonrpc::error::RpcError;
+    use bitcoincore_rpc::{json, Error, RawTx, Result};
+    use serde::de::DeserializeOwned;
+    use serde_json::{json as value, Value};
+    use std::collections::{HashMap, HashSet};
+
+    // A wallet transaction and where it stands.
+    struct MockTx {
+        tx: Transaction,
+        // Hash and height of the confirming block, if confirmed.
+        block: Option<(BlockHash, u64)>,
+        // What the wallet reports as the fee; only known for transactions it sent.
+        fee: Option<Amount>,
+    }
+
+    pub(crate) struct MockBackend {
+        network: Network,
+        balance: Amount,
+        transactions: HashMap<Txid, MockTx>,
+        // Addresses `getaddressinfo` reports as the wallet's own.
+        mine: HashSet<Address>,
+        // Outputs `gettxout` finds in the UTXO set; it reports every other one spent.
+        unspent: HashMap<OutPoint, TxOut>,
+        // Transactions that aren't the wallet's but the node finds through its txindex.
+        indexed: HashMap<Txid, Transaction>,
+    }
+
+    impl MockBackend {
+        pub(crate) fn new(network: Network) -> Self {
+            MockBackend {
+                network,
+                balance: Amount::ZERO,
+                transactions: HashMap::new(),
+                mine: HashSet::new(),
+                unspent: HashMap::new(),
+                indexed: HashMap::new(),
+            }
+        }
+
+        // Spendable balance reported by `getbalance` and `getbalances`.
+        pub(crate) fn with_balance(mut self, balance: Amount) -> Self {
+            self.balance = balance;
+            self
+        }
+
+        // Adds `tx` to the wallet, confirmed at `block` if given.
+        pub(crate) fn with_transaction(
+            mut self,
+            tx: Transaction,
+            block: Option<(BlockHash, u64)>,
+            fee: Option<Amount>,
+        ) -> Self {
+            self.transactions
+                .insert(tx.txid(), MockTx { tx, block, fee });
+            self
+        }
+
+        // Makes `getaddressinfo` answer `ismine: true` for `address`.
+        pub(crate) fn owning(mut self, address: &Address) -> Self {
+            self.mine.insert(address.clone());
+            self
+        }
+
+        // Makes `gettxout` find `output` unspent at `outpoint`.
+        pub(crate) fn with_unspent(mut self, outpoint: OutPoint, output: TxOut) -> Self {
+            self.unspent.insert(outpoint, output);
+            self
+        }
+
+        // Makes the node run a txindex that finds `tx`, which isn't the wallet's.
+        pub(crate) fn with_indexed_transaction(mut self, tx: Transaction) -> Self {
+            self.indexed.insert(tx.txid(), tx);
+            self
+        }
+
+        fn wallet_tx(&self, txid: &Txid) -> Result<&MockTx> {
+            self.transactions.get(txid).ok_or_else(|| {
+                Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Rpc(RpcError {
+                    code: -5,
+                    message: "Invalid or non-wallet transaction id".to_owned(),
+                    data: None,
+                }))
+            })
+        }
+
+        fn decoded(&self, tx: &Transaction) -> Value {
+            let vin: Vec<Value> = tx
+                .input
+                .iter()
+                .map(|input| {
+                    value!({
+                        "txid": input.previous_output.txid,
+                        "vout": input.previous_output.vout,
+                        "sequence": input.sequence.0,
+                    })
+                })
+                .collect();
+            let vout: Vec<Value> = tx
+                .output
+                .iter()
+                .enumerate()
+                .map(|(n, output)| {
+                    value!({
+                        "value": output.value.to_btc(),
+                        "n": n,
+                        "scriptPubKey": self.script_pub_key(output),
+                    })
+                })
+                .collect();
+            value!({
+                "txid": tx.txid(),
+                "hash": tx.wtxid(),
+                "size": tx.total_size(),
+                "vsize": tx.vsize(),
+                "weight": tx.weight().to_wu(),
+                "version": tx.version.0,
+                "locktime": tx.lock_time.to_consensus_u32(),
+                "vin": vin,
+                "vout": vout,
+            })
+        }
+
+        fn script_pub_key(&self, output: &TxOut) -> Value {
+            let address = Address::from_script(&output.script_pubkey, self.network).ok();
+            value!({
+                "asm": "",
+                "hex": output.script_pubkey.as_bytes().to_lower_hex_string(),
+                "address": address.map(|a| a.to_string()),
+            })
+        }
+    }
+
+    fn unanswered<T>(method: &str) -> Result<T> {
+        Err(Error::ReturnedError(format!(
+            "the mock backend doesn't answer {method}"
+        )))
+    }
+
+    fn from_value<T: DeserializeOwned>(value: Value) -> Result<T> {
+        Ok(serde_json::from_value(value)?)
+    }
+
+    impl RpcBackend for MockBackend {
+        fn call<T: DeserializeOwned>(&self, method: &str, _args: &[Value]) -> Result<T> {
+            match method {
+                // Without indexed transactions there is no txindex, so only the wallet's own
+                // transactions can be looked up.
+                "getindexinfo" if self.indexed.is_empty() => from_value(value!({})),
+                "getindexinfo" => from_value(value!({
+                    "txindex": { "synced": true, "best_block_height": 0 },
+                })),
+                _ => unanswered(method),
+            }
+        }
+
+        fn get_blockchain_info(&self) -> Result<json::GetBlockchainInfoResult> {
+            unanswered("getblockchaininfo")
+        }
+
+        fn get_block_count(&self) -> Result<u64> {
+            unanswered("getblockcount")
+        }
+
+        fn get_block_hash(&self, _height: u64) -> Result<BlockHash> {
+            unanswered("getblockhash")
+        }
+
+        fn get_block(&self, _hash: &BlockHash) -> Result<Block> {
+            unanswered("getblock")
+        }
+
+        fn get_block_info(&self, hash: &BlockHash) -> Result<json::GetBlockResult> {
+            let Some((_, height)) = self
+                .transactions
+                .values()
+                .filter_map(|tx| tx.block)
+                .find(|(block_hash, _)| block_hash == hash)
+            else {
+                return unanswered("getblock");
+            };
+            let txids: Vec<Txid> = self
+                .transactions
+                .iter()
+                .filter(|(_, tx)| tx.block.is_some_and(|(block_hash, _)| block_hash == *hash))
+                .map(|(txid, _)| *txid)
+                .collect();
+            from_value(value!({
+                "hash": hash,
+                "confirmations": 1,
+                "size": 0,
+                "weight": 0,
+                "height": height,
+                "version": 0,
+                "merkleroot": "0000000000000000000000000000000000000000000000000000000000000000",
+                "nTx": txids.len(),
+                "tx": txids,
+                "time": 0,
+                "nonce": 0,
+                "bits": "207fffff",
+                "difficulty": 0.0,
+                "chainwork": "00",
+            }))
+        }
+
+        fn get_raw_transaction(
+            &self,
+            txid: &Txid,
+            _block_hash: Option<&BlockHash>,
+        ) -> Result<Transaction> {
+            match self.indexed.get(txid) {
+                Some(tx) => Ok(tx.clone()),
+                None => Ok(self.wallet_tx(txid)?.tx.clone()),
+            }
+        }
+
+        fn get_raw_transactions(&self, txids: &[Txid]) -> Result<Vec<Transaction>> {
+            txids
+                .iter()
+                .map(|txid| self.get_raw_transaction(txid, None))
+                .collect()
+        }
+
+        fn decode_raw_transaction<R: RawTx>(
+            &self,
+            tx: R,
+            _is_witness: Option<bool>,
+        ) -> Result<json::DecodeRawTransactionResult> {
+            let tx: Transaction = deserialize(&Vec::from_hex(&tx.raw_hex())?)?;
+            from_value(self.decoded(&tx))
+        }
+
+        fn get_tx_out(
+            &self,
+            txid: &Txid,
+            vout: u32,
+            _include_mempool: Option<bool>,
+        ) -> Result<Option<json::GetTxOutResult>> {
+            let Some(output) = self.unspent.get(&OutPoint { txid: *txid, vout }) else {
+                return Ok(None);
+            };
+            from_value(value!({
+                "bestblock": BlockHash::all_zeros(),
+                "confirmations": 1,
+                "value": output.value.to_btc(),
+                "scriptPubKey": self.script_pub_key(output),
+                "coinbase": false,
+            }))
+        }
+
+        fn get_raw_mempool(&self) -> Result<Vec<Txid>> {
+            unanswered("getrawmempool")
+        }
+
+        fn get_mempool_entry(&self, _txid: &Txid) -> Result<json::GetMempoolEntryResult> {
+            unanswered("getmempoolentry")
+        }
+
+        fn estimate_smart_fee(
+            &self,
+            _conf_target: u16,
+            _estimate_mode: Option<json::EstimateMode>,
+        ) -> Result<json::EstimateSmartFeeResult> {
+            unanswered("estimatesmartfee")
+        }
+
+        fn send_raw_transaction<R: RawTx>(&self, _tx: R) -> Result<Txid> {
+            unanswered("sendrawtransaction")
+        }
+
+        fn list_wallets(&self) -> Result<Vec<String>> {
+            unanswered("listwallets")
+        }
+
+        fn list_wallet_dir(&self) -> Result<Vec<String>> {
+            unanswered("listwalletdir")
+        }
+
+        fn create_wallet(
+            &self,
+            _wallet: &str,
+            _disable_private_keys: Option<bool>,
+            _blank: Option<bool>,
+            _passphrase: Option<&str>,
+            _avoid_reuse: Option<bool>,
+        ) -> Result<json::LoadWalletResult> {
+            unanswered("createwallet")
+        }
+
+        fn load_wallet(&self, _wallet: &str) -> Result<json::LoadWalletResult> {
+            unanswered("loadwallet")
+        }
+
+        fn get_wallet_info(&self) -> Result<json::GetWalletInfoResult> {
+            unanswered("getwalletinfo")
+        }
+
+        fn get_new_address(
+            &self,
+            _label: Option<&str>,
+            _address_type: Option<json::AddressType>,
+        ) -> Result<Address<NetworkUnchecked>> {
+            unanswered("getnewaddress")
+        }
+
+        fn get_address_info(&self, address: &Address) -> Result<json::GetAddressInfoResult> {
+            from_value(value!({
+                "address": address,
+                "scriptPubKey": address.script_pubkey().as_bytes().to_lower_hex_string(),
+                "ismine": self.mine.contains(address),
+                "iswatchonly": false,
+                "labels": [],
+            }))
+        }
+
+        fn get_balance(
+            &self,
+            _minconf: Option<usize>,
+            _include_watchonly: Option<bool>,
+        ) -> Result<Amount> {
+            Ok(self.balance)
+        }
+
+        fn get_balances(&self) -> Result<json::GetBalancesResult> {
+            from_value(value!({
+                "mine": {
+                    "trusted": self.balance.to_btc(),
+                    "untrusted_pending": 0.0,
+                    "immature": 0.0,
+                },
+            }))
+        }
+
+        fn list_unspent(
+            &self,
+            _minconf: Option<usize>,
+            _maxconf: Option<usize>,
+            _addresses: Option<&[&Address]>,
+            _include_unsafe: Option<bool>,
+            _query_options: Option<json::ListUnspentQueryOptions>,
+        ) -> Result<Vec<json::ListUnspentResultEntry>> {
+            unanswered("listunspent")
+        }
+
+        fn get_transaction(
+            &self,
+            txid: &Txid,
+            _include_watchonly: Option<bool>,
+        ) -> Result<json::GetTransactionResult> {
+            let wallet_tx = self.wallet_tx(txid)?;
+            let (blockhash, blockheight) = wallet_tx.block.unzip();
+            from_value(value!({
+                "confirmations": i32::from(wallet_tx.block.is_some()),
+                "blockhash": blockhash,
+                "blockheight": blockheight,
+                "txid": txid,
+                "time": 0,
+                "timereceived": 0,
+                "bip125-replaceable": "no",
+                "walletconflicts": [],
+                "amount": 0.0,
+                // The wallet reports the fee of a transaction it sent as a negative amount.
+                "fee": wallet_tx.fee.map(|fee| -fee.to_btc()),
+                "details": [],
+                "hex": serialize_hex(&wallet_tx.tx),
+            }))
+        }
+
+        fn list_transactions(
+            &self,
+            _label: Option<&str>,
+            _count: Option<usize>,
+            _skip: Option<usize>,
+            _include_watchonly: Option<bool>,
+        ) -> Result<Vec<json::ListTransactionResult>> {
+            unanswered("listtransactions")
+        }
+
+        fn generate_to_address(
+            &self,
+            _block_num: u64,
+            _address: &Address,
+        ) -> Result<Vec<BlockHash>> {
+            unanswered("generatetoaddress")
+        }
+
+        fn create_raw_transaction_hex(
+            &self,
+            _utxos: &[json::CreateRawTransactionInput],
+            _outs: &HashMap<String, Amount>,
+            _locktime: Option<i64>,
+            _replaceable: Option<bool>,
+        ) -> Result<String> {
+            unanswered("createrawtransaction")
+        }
+
+        fn fund_raw_transaction<R: RawTx>(
+            &self,
+            _tx: R,
+            _options: Option<&json::FundRawTransactionOptions>,
+            _is_witness: Option<bool>,
+        ) -> Result<json::FundRawTransactionResult> {
+            unanswered("fundrawtransaction")
+        }
+
+        fn sign_raw_transaction_with_wallet<R: RawTx>(
+            &self,
+            _tx: R,
+            _utxos: Option<&[json::SignRawTransactionInput]>,
+            _sighash_type: Option<json::SigHashType>,
+        ) -> Result<json::SignRawTransactionResult> {
+            unanswered("signrawtransactionwithwallet")
+        }
+
+        fn wallet_create_funded_psbt(
+            &self,
+            _inputs: &[json::CreateRawTransactionInput],
+            _outputs: &HashMap<String, Amount>,
+            _locktime: Option<i64>,
+            _options: Option<json::WalletCreateFundedPsbtOptions>,
+            _bip32derivs: Option<bool>,
+        ) -> Result<json::WalletCreateFundedPsbtResult> {
+            unanswered("walletcreatefundedpsbt")
+        }
+
+        fn wallet_process_psbt(
+            &self,
+            _psbt: &str,
+            _sign: Option<bool>,
+            _sighash_type: Option<json::SigHashType>,
+            _bip32derivs: Option<bool>,
+        ) -> Result<json::WalletProcessPsbtResult> {
+            unanswered("walletprocesspsbt")
+        }
+
+        fn finalize_psbt(
+            &self,
+            _psbt: &str,
+            _extract: Option<bool>,
+        ) -> Result<json::FinalizePsbtResult> {
+            unanswered("finalizepsbt")
+        }
+    }
+}
@@ -0,0 +1,146 @@
+// Timing the setup, send and report flow phase by phase.
+
+use crate::backend::RpcBackend;
+use crate::cli::BenchArgs;
+use crate::error::AppError;
+use crate::mining::{run_setup, MIN_SPENDABLE_BALANCE};
+use crate::report::{build_report, TraderOutput};
+use crate::tx::run_send;
+use crate::wallet::Wallets;
+use bitcoincore_rpc::bitcoin::Network;
+use log::info;
+use std::time::{Duration, Instant};
+
+// How long each phase of one round took.
+#[derive(Debug, Clone, Copy, Default)]
+struct PhaseTimes {
+    // Mining until the Miner has a spendable balance.
+    mining: Duration,
+    // Building, signing and broadcasting the transaction.
+    sending: Duration,
+    // Mining the confirmation blocks and waiting for the wallet to see them.
+    confirming: Duration,
+    // Gathering the report details from the node.
+    reporting: Duration,
+}
+
+impl PhaseTimes {
+    fn columns(&self) -> [Duration; 4] {
+        [self.mining, self.sending, self.confirming, self.reporting]
+    }
+}
+
+// Runs setup, send and report `args.rounds` times and prints how many milliseconds each
+// phase took per round, with the mean over all rounds. Nothing is written to out.txt.
+pub(crate) fn run_bench(
+    rpc: &impl RpcBackend,
+    wallets: &Wallets<impl RpcBackend>,
+    network: Network,
+    args: &BenchArgs,
+) -> Result<(), AppError> {
+    if network != Network::Regtest {
+        return Err(AppError::Config(
+            "bench mines blocks, so it only runs against a regtest node".to_owned(),
+        ));
+    }
+    let mut rounds = Vec::new();
+    for round in 1..=args.rounds {
+        info!("Benchmark round {round} of {}", args.rounds);
+        let start = Instant::now();
+        let mining_address = run_setup(
+            wallets,
+            network,
+            args.send.address_type,
+            MIN_SPENDABLE_BALANCE,
+            args.send.use_generateblock,
+        )?;
+        let mining = start.elapsed();
+        let start = Instant::now();
+        let sent = run_send(rpc, wallets, network, &args.send, &mining_address)?;
+        let send_and_confirm = start.elapsed();
+        let start = Instant::now();
+        build_report(
+            wallets,
+            &sent.txid,
+            TraderOutput::Addresses(&sent.trader_addresses),
+            network,
+        )?;
+        rounds.push(PhaseTimes {
+            mining,
+            sending: send_and_confirm.saturating_sub(sent.confirm_time),
+            confirming: sent.confirm_time,
+            reporting: start.elapsed(),
+        });
+    }
+    print_bench_table(&rounds);
+    Ok(())
+}
+
+fn print_bench_table(rounds: &[PhaseTimes]) {
+    println!(
+        "{:<6} {:>12} {:>12} {:>15} {:>14}",
+        "ROUND", "MINING (ms)", "SENDING (ms)", "CONFIRMING (ms)", "REPORTING (ms)"
+    );
+    for (i, times) in rounds.iter().enumerate() {
+        print_bench_row(&(i + 1).to_string(), times.columns());
+    }
+    print_bench_row("mean", mean(rounds).columns());
+}
+
+fn print_bench_row(label: &str, [mining, sending, confirming, reporting]: [Duration; 4]) {
+    println!(
+        "{label:<6} {:>12} {:>12} {:>15} {:>14}",
+        mining.as_millis(),
+        sending.as_millis(),
+        confirming.as_millis(),
+        reporting.as_millis()
+    );
+}
+
+// Mean time of each phase over `rounds`.
+fn mean(rounds: &[PhaseTimes]) -> PhaseTimes {
+    let n = match u32::try_from(rounds.len()) {
+        Ok(n) if n > 0 => n,
+        _ => return PhaseTimes::default(),
+    };
+    let total = rounds
+        .iter()
+        .fold(PhaseTimes::default(), |sum, t| PhaseTimes {
+            mining: sum.mining + t.mining,
+            sending: sum.sending + t.sending,
+            confirming: sum.confirming + t.confirming,
+            reporting: sum.reporting + t.reporting,
+        });
+    PhaseTimes {
+        mining: total.mining / n,
+        sending: total.sending / n,
+        confirming: total.confirming / n,
+        reporting: total.reporting / n,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mean_averages_each_phase() {
+        let ms = Duration::from_millis;
+        let rounds = [
+            PhaseTimes {
+                mining: ms(100),
+                sending: ms(10),
+                confirming: ms(40),
+                reporting: ms(2),
+            },
+            PhaseTimes {
+                mining: ms(300),
+                sending: ms(30),
+                confirming: ms(60),
+                reporting: ms(4),
+            },
+        ];
+        assert_eq!(mean(&rounds).columns(), [ms(200), ms(20), ms(50), ms(3)]);
+        assert_eq!(mean(&[]).columns(), [Duration::ZERO; 4]);
+    }
+}
@@ -0,0 +1,614 @@
+// Command-line arguments and their value parsers.
+
+use crate::config::JsonRpcVersion;
+use crate::out::Unit;
+use bitcoin::Psbt;
+use bitcoincore_rpc::bitcoin::address::NetworkUnchecked;
+use bitcoincore_rpc::bitcoin::consensus::encode::deserialize;
+use bitcoincore_rpc::bitcoin::hex::FromHex;
+use bitcoincore_rpc::bitcoin::{
+    Address, Amount, BlockHash, Denomination, FeeRate, Network, Transaction, Txid,
+};
+use bitcoincore_rpc::json::AddressType;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+const DEFAULT_MINER_WALLET: &str = "Miner";
+const DEFAULT_TRADER_WALLET: &str = "Trader";
+const DEFAULT_SEND_AMOUNT_BTC: &str = "20";
+// Relative to the working directory; `run-rust.sh` runs the binary from `rust/`.
+const DEFAULT_OUT_PATH: &str = "../out.txt";
+
+// The exit codes of AppError::exit_code, for `--help`.
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  success
+  1  RPC or connection error (and any failure not listed below)
+  2  insufficient funds
+  3  invalid arguments or configuration
+  4  verification failed (selftest, reorg or balance check)
+130  interrupted (Ctrl-C) while mining or waiting for a block";
+
+// Command-line interface. Without a subcommand the whole flow runs, as `run-all` would, so
+// `run-all`'s flags are accepted at the top level too.
+#[derive(Debug, Parser)]
+#[command(about = "Mine, send and report on a Miner -> Trader payment through Bitcoin Core")]
+#[command(args_conflicts_with_subcommands = true, after_help = EXIT_CODES_HELP)]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub run_all: RunAllArgs,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Create or load the wallets and mine until the miner has a spendable balance
+    Setup(SetupArgs),
+    /// Send from the miner to a new trader address and wait for it to confirm
+    Send(SendArgs),
+    /// Write the details of an existing Miner -> Trader transaction
+    Report(ReportArgs),
+    /// Setup, send and report in one go (the default)
+    RunAll(RunAllArgs),
+    /// Print the reward, extranonce and payout address of a block's coinbase transaction
+    InspectCoinbase(InspectCoinbaseArgs),
+    /// Run setup, send and report, then check every line of out.txt against the node
+    Selftest(SelftestArgs),
+    /// List the miner's unspent outputs with their amounts, confirmations and addresses
+    Utxos,
+    /// List the addresses of the miner and trader wallets grouped by label
+    ListAddresses,
+    /// List the most recent transactions of the miner or trader wallet, newest first
+    Transactions(TransactionsArgs),
+    /// Show how much the trader's "Received" addresses have received, whatever its balance
+    Received(ReceivedArgs),
+    /// Replace an unconfirmed miner transaction with one paying a higher fee (bumpfee)
+    BumpFee(TxidArgs),
+    /// Let the miner wallet forget an unconfirmed transaction and spend its inputs again
+    Abandon(TxidArgs),
+    /// Show which inputs of a PSBT are signed, its estimated fee and size, and who acts next
+    AnalyzePsbt(AnalyzePsbtArgs),
+    /// Time mining, sending, confirming and reporting against a local regtest node
+    Bench(BenchArgs),
+    /// Check that the node has finished starting up and is out of initial block download
+    Healthcheck,
+    /// Show the inputs, outputs and size of a raw transaction, and which outputs are the wallets'
+    Decode(DecodeArgs),
+}
+
+// Flags every subcommand shares.
+#[derive(Debug, Clone, clap::Args)]
+pub struct GlobalArgs {
+    /// Wallet that mines blocks and funds the send
+    #[arg(long, global = true, default_value = DEFAULT_MINER_WALLET, value_parser = parse_wallet_name)]
+    pub(crate) miner_wallet: String,
+    /// Wallet that receives the payment
+    #[arg(long, global = true, default_value = DEFAULT_TRADER_WALLET, value_parser = parse_wallet_name)]
+    pub(crate) trader_wallet: String,
+    /// Chain the node runs: regtest, signet or testnet. Only regtest blocks can be mined here;
+    /// on signet and testnet other miners produce them (wait for them with send --watch)
+    #[arg(long, global = true, default_value = "regtest", value_parser = parse_network)]
+    pub(crate) network: Network,
+    /// Authenticate with this bitcoind cookie file instead of BITCOIN_RPC_USER/BITCOIN_RPC_PASS
+    #[arg(long, global = true, value_name = "PATH")]
+    pub(crate) cookie: Option<PathBuf>,
+    /// Trust the CA certificate(s) in this PEM file instead of the usual web roots when
+    /// BITCOIN_RPC_URL is https://, e.g. for a proxy with a self-signed certificate
+    #[arg(long, global = true, value_name = "PATH")]
+    pub(crate) ca_cert: Option<PathBuf>,
+    /// Record every RPC request and response to this file as JSON lines (credentials left out)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub(crate) rpc_trace: Option<PathBuf>,
+    /// Answer every RPC request from a file written by --rpc-trace instead of a node, to repeat
+    /// a recorded run offline
+    #[arg(long, global = true, value_name = "PATH")]
+    pub(crate) replay: Option<PathBuf>,
+    /// Use the cookie in bitcoind's default datadir if there is one, else BITCOIN_RPC_USER/PASS
+    #[arg(long, global = true, conflicts_with = "cookie")]
+    pub(crate) auto: bool,
+    /// JSON-RPC version to frame requests with: 2.0, or 1.0 for proxies that reject 2.0
+    #[arg(long, global = true, value_name = "VERSION", default_value = "2.0", value_parser = parse_json_rpc_version)]
+    pub(crate) json_rpc_version: JsonRpcVersion,
+    /// Seconds to wait for the node to answer a request before giving up
+    #[arg(long, global = true, value_name = "SECS", default_value = "30", value_parser = parse_secs)]
+    pub(crate) timeout_secs: Duration,
+    /// Connections to fetch the transactions a report's inputs spend over in parallel, used
+    /// when the node doesn't accept them as one JSON-RPC batch
+    #[arg(long, global = true, value_name = "N", default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+    pub(crate) rpc_concurrency: u32,
+    /// Print a summary of the chain and its UTXO set, then exit
+    #[arg(long, global = true)]
+    pub(crate) info: bool,
+    /// Only log errors, and show no progress bars
+    #[arg(long, short, global = true, conflicts_with = "verbose")]
+    pub(crate) quiet: bool,
+    /// Log more: -v adds debug output such as the blockchain info and every transaction
+    /// output, -vv adds the RPC library's debug output, -vvv traces everything
+    #[arg(long, short, global = true, action = clap::ArgAction::Count)]
+    pub(crate) verbose: u8,
+    #[command(flatten)]
+    pub(crate) trader_wallet_options: WalletOptions,
+}
+
+// `createwallet` options. Only the Trader wallet's are configurable: the Miner wallet has to
+// sign, so it always gets a regular wallet with private keys. The options only apply when the
+// wallet is created; an existing wallet is loaded as it is.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct WalletOptions {
+    /// Create the trader wallet without private keys, i.e. watch-only
+    #[arg(long = "trader-watch-only", global = true)]
+    pub(crate) disable_private_keys: bool,
+    /// Create the trader wallet blank, without any keys or descriptors
+    #[arg(long = "trader-blank", global = true)]
+    pub(crate) blank: bool,
+    /// Encrypt a newly created trader wallet with this passphrase
+    #[arg(long = "trader-passphrase", global = true, value_name = "PASSPHRASE")]
+    pub(crate) passphrase: Option<String>,
+    /// Create the trader wallet with avoid_reuse, keeping spent-to addresses apart
+    #[arg(long = "trader-avoid-reuse", global = true)]
+    pub(crate) avoid_reuse: bool,
+}
+
+impl GlobalArgs {
+    // The log filter `--quiet`/`--verbose` ask for, used unless RUST_LOG says otherwise.
+    pub fn default_log_filter(&self) -> &'static str {
+        if self.quiet {
+            return "error";
+        }
+        match self.verbose {
+            0 => "info",
+            // Only this crate's debug output; the RPC library logs every request at debug.
+            1 => "info,rust=debug",
+            2 => "debug",
+            _ => "trace",
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.miner_wallet == self.trader_wallet {
+            return Err(format!(
+                "miner and trader wallets must differ (both are '{}')",
+                self.miner_wallet
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct SetupArgs {
+    /// Type of the generated addresses: legacy, p2sh-segwit, bech32 or bech32m
+    #[arg(long, default_value = "bech32m", value_parser = parse_address_type)]
+    pub(crate) address_type: AddressType,
+    /// Keep mining until the miner can spend this many BTC [default: any positive balance]
+    #[arg(long, value_name = "BTC", value_parser = parse_btc)]
+    pub(crate) min_balance: Option<Amount>,
+    /// Mine exactly as many blocks as it takes to bring the chain to this height instead of
+    /// mining for a balance
+    #[arg(long, value_name = "HEIGHT", conflicts_with = "min_balance")]
+    pub(crate) to_height: Option<u64>,
+    /// Mine with generateblock, one block per call holding no transactions, instead of
+    /// generatetoaddress
+    #[arg(long)]
+    pub(crate) use_generateblock: bool,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct SendArgs {
+    /// Amount the miner sends to the trader, in BTC
+    #[arg(long, default_value = DEFAULT_SEND_AMOUNT_BTC, value_parser = parse_btc)]
+    pub(crate) send_amount: Amount,
+    /// Blocks to mine on top of the send
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub(crate) confirmations: u32,
+    /// Type of the generated addresses: legacy, p2sh-segwit, bech32 or bech32m
+    #[arg(long, default_value = "bech32m", value_parser = parse_address_type)]
+    pub(crate) address_type: AddressType,
+    /// Build the send with create/fund/sign/sendrawtransaction instead of sendtoaddress
+    #[arg(long)]
+    pub(crate) manual_tx: bool,
+    /// Build the send as a PSBT: walletcreatefundedpsbt, walletprocesspsbt, finalizepsbt, then
+    /// sendrawtransaction
+    #[arg(long, conflicts_with = "manual_tx")]
+    pub(crate) psbt: bool,
+    /// Mine with generateblock instead of generatetoaddress; the first confirmation block then
+    /// holds only the send
+    #[arg(long)]
+    pub(crate) use_generateblock: bool,
+    /// Wait for the send to be mined by someone else, logging changes to its mempool entry
+    #[arg(long)]
+    pub(crate) watch: bool,
+    /// Seconds between mempool polls with --watch
+    #[arg(long, value_name = "SECS", default_value = "5", value_parser = parse_secs)]
+    pub(crate) watch_interval: Duration,
+    /// Fee rate of the send in sat/vB [default: estimatesmartfee for --conf-target]
+    #[arg(long, value_name = "SAT/VB", value_parser = parse_fee_rate)]
+    pub(crate) fee_rate: Option<FeeRate>,
+    /// Confirmation target in blocks for the fee estimate when --fee-rate isn't given
+    #[arg(long, default_value_t = 6, conflicts_with = "fee_rate")]
+    pub(crate) conf_target: u16,
+    /// Import this descriptor into the trader wallet and pay its first address instead of a
+    /// new one, so every run uses the same trader address
+    #[arg(long, value_name = "DESCRIPTOR", conflicts_with = "to")]
+    pub(crate) trader_descriptor: Option<String>,
+    /// Show which of the miner's coins would fund the send, its change and fee, then exit
+    /// without sending anything
+    #[arg(long)]
+    pub(crate) preview: bool,
+    /// Mine more blocks first if the miner can't cover the send and its estimated fee
+    #[arg(long)]
+    pub(crate) auto_fund: bool,
+    /// Send even if a payment or the change is dust (worth less than the fee to spend it),
+    /// warning instead of refusing
+    #[arg(long)]
+    pub(crate) allow_dust: bool,
+    /// Pay AMOUNT BTC to ADDR instead of a new trader address; repeat to pay several
+    /// recipients in one transaction
+    #[arg(long, value_name = "ADDR:AMOUNT", value_parser = parse_recipient, conflicts_with = "send_amount")]
+    pub(crate) to: Vec<Recipient>,
+}
+
+impl SendArgs {
+    // What the send pays out in total, before fees.
+    pub(crate) fn total_amount(&self) -> Amount {
+        if self.to.is_empty() {
+            self.send_amount
+        } else {
+            self.to.iter().map(|recipient| recipient.amount).sum()
+        }
+    }
+}
+
+// A `--to ADDR:AMOUNT` payment.
+#[derive(Debug, Clone)]
+pub struct Recipient {
+    pub(crate) address: Address<NetworkUnchecked>,
+    pub(crate) amount: Amount,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct ReportArgs {
+    /// Confirmed Miner -> Trader transaction to report on
+    #[arg(long)]
+    pub(crate) txid: Txid,
+    /// Trader address the transaction pays [default: any address of the trader wallet]
+    #[arg(long)]
+    pub(crate) trader_address: Option<Address<NetworkUnchecked>>,
+    /// Check that the block height and hash still agree with the active chain before writing
+    #[arg(long)]
+    pub(crate) verify: bool,
+    #[command(flatten)]
+    pub(crate) output: OutputArgs,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct TxidArgs {
+    /// Unconfirmed transaction of the miner wallet
+    pub(crate) txid: Txid,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct AnalyzePsbtArgs {
+    /// Base64-encoded PSBT; read from stdin if omitted or `-`
+    #[arg(value_name = "BASE64")]
+    pub(crate) psbt: Option<String>,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct TransactionsArgs {
+    /// Wallet whose transactions to list: miner or trader
+    #[arg(long, default_value = "miner")]
+    pub(crate) wallet: WalletRole,
+    /// How many transactions to list
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    pub(crate) count: usize,
+    /// How many of the most recent transactions to leave out, to page back through the history
+    #[arg(long, value_name = "M", default_value_t = 0)]
+    pub(crate) skip: usize,
+}
+
+// One of the two wallets the tool works with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalletRole {
+    Miner,
+    Trader,
+}
+
+impl FromStr for WalletRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "miner" => Ok(WalletRole::Miner),
+            "trader" => Ok(WalletRole::Trader),
+            _ => Err(format!(
+                "invalid wallet '{s}': expected one of miner, trader"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct ReceivedArgs {
+    /// Only count transactions with at least this many confirmations (0 counts the mempool)
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub(crate) min_conf: u32,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct DecodeArgs {
+    /// Raw transaction in hex; read from stdin if omitted or `-`
+    #[arg(value_name = "HEX")]
+    pub(crate) hex: Option<String>,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct InspectCoinbaseArgs {
+    /// Height or hash of the block
+    #[arg(value_name = "HEIGHT_OR_HASH", value_parser = parse_block_ref)]
+    pub(crate) block: BlockRef,
+}
+
+// A block, given by height or by hash.
+#[derive(Debug, Clone, Copy)]
+pub enum BlockRef {
+    Height(u64),
+    Hash(BlockHash),
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct RunAllArgs {
+    #[command(flatten)]
+    pub(crate) send: SendArgs,
+    #[command(flatten)]
+    pub(crate) output: OutputArgs,
+    /// Print the wallet, mining and send calls a run would make, then exit
+    #[arg(long)]
+    pub(crate) dry_run: bool,
+    /// Send again even if the output file already describes a confirmed transaction
+    #[arg(long)]
+    pub(crate) force: bool,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct SelftestArgs {
+    #[command(flatten)]
+    pub(crate) send: SendArgs,
+    /// Where to write and then re-read the transaction details
+    #[arg(long, default_value = DEFAULT_OUT_PATH)]
+    pub(crate) out: PathBuf,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct BenchArgs {
+    #[command(flatten)]
+    pub(crate) send: SendArgs,
+    /// How many times to run setup, send and report
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    pub(crate) rounds: u32,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct OutputArgs {
+    /// Where to write the transaction details
+    #[arg(long, default_value = DEFAULT_OUT_PATH)]
+    pub(crate) out: PathBuf,
+    /// Which outputs to write: text (out.txt), json (out.json next to it) or both
+    #[arg(long, default_value = "both")]
+    pub(crate) format: OutputFormat,
+    /// Unit of the amounts in out.txt and out.json: btc (8 decimal places) or sat
+    #[arg(long, value_name = "UNIT", default_value = "btc")]
+    pub(crate) amount_unit: Unit,
+    /// Also write the raw transaction hex, with and without witness data, to tx.hex next to it
+    #[arg(long)]
+    pub(crate) emit_hex: bool,
+    /// Don't print the summary table of the transaction at the end
+    #[arg(long)]
+    pub(crate) no_summary: bool,
+    /// Add an 11th line to out.txt with the fee rate in sat/vB, one decimal place
+    #[arg(long)]
+    pub(crate) emit_feerate: bool,
+    /// Add a line to out.txt, after the fee rate if there is one, with the type of the Miner's
+    /// change address: legacy, p2sh-segwit, bech32 or bech32m
+    #[arg(long)]
+    pub(crate) emit_address_type: bool,
+}
+
+// Which report files a run produces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Both,
+}
+
+impl OutputFormat {
+    pub(crate) fn text(self) -> bool {
+        matches!(self, OutputFormat::Text | OutputFormat::Both)
+    }
+
+    pub(crate) fn json(self) -> bool {
+        matches!(self, OutputFormat::Json | OutputFormat::Both)
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "both" => Ok(OutputFormat::Both),
+            _ => Err(format!(
+                "invalid format '{s}': expected one of text, json, both"
+            )),
+        }
+    }
+}
+
+// Parses a positive BTC amount such as `20` or `0.5`.
+fn parse_btc(s: &str) -> Result<Amount, String> {
+    match Amount::from_str_in(s, Denomination::Bitcoin) {
+        Ok(amount) if amount > Amount::ZERO => Ok(amount),
+        Ok(_) => Err(format!("invalid amount '{s}': must be greater than zero")),
+        Err(e) => Err(format!("invalid amount '{s}': {e}")),
+    }
+}
+
+// Parses a block height (all digits) or a block hash.
+fn parse_block_ref(s: &str) -> Result<BlockRef, String> {
+    if s.chars().all(|c| c.is_ascii_digit()) {
+        s.parse()
+            .map(BlockRef::Height)
+            .map_err(|e| format!("invalid block height '{s}': {e}"))
+    } else {
+        s.parse()
+            .map(BlockRef::Hash)
+            .map_err(|e| format!("invalid block hash '{s}': {e}"))
+    }
+}
+
+// Parses a positive number of seconds.
+fn parse_secs(s: &str) -> Result<Duration, String> {
+    match s.parse::<u64>() {
+        Ok(secs) if secs > 0 => Ok(Duration::from_secs(secs)),
+        _ => Err(format!(
+            "invalid duration '{s}': expected a positive number of seconds"
+        )),
+    }
+}
+
+// Parses a positive fee rate in sat/vB such as `1` or `2.5`.
+fn parse_fee_rate(s: &str) -> Result<FeeRate, String> {
+    match s.parse::<f64>() {
+        // Core accepts fee rates to the thousandth of a sat/vB.
+        Ok(sat_per_vb) if sat_per_vb > 0.0 && sat_per_vb.is_finite() => Ok(
+            FeeRate::from_sat_per_kwu((sat_per_vb * 250.0).round() as u64),
+        ),
+        _ => Err(format!(
+            "invalid fee rate '{s}': expected a positive number of sat/vB"
+        )),
+    }
+}
+
+// Parses `ADDR:AMOUNT`, with AMOUNT in BTC.
+fn parse_recipient(s: &str) -> Result<Recipient, String> {
+    let (address, amount) = s
+        .rsplit_once(':')
+        .ok_or_else(|| format!("invalid recipient '{s}': expected ADDR:AMOUNT"))?;
+    let address = address
+        .parse()
+        .map_err(|e| format!("invalid recipient address '{address}': {e}"))?;
+    Ok(Recipient {
+        address,
+        amount: parse_btc(amount)?,
+    })
+}
+
+// Parses a base64-encoded PSBT.
+pub(crate) fn parse_psbt(s: &str) -> Result<Psbt, String> {
+    s.trim().parse().map_err(|e| format!("invalid PSBT: {e}"))
+}
+
+// Parses a hex-encoded raw transaction.
+pub(crate) fn parse_raw_tx(s: &str) -> Result<Transaction, String> {
+    let bytes = Vec::from_hex(s.trim()).map_err(|e| format!("invalid transaction hex: {e}"))?;
+    deserialize(&bytes).map_err(|e| format!("invalid raw transaction: {e}"))
+}
+
+// Parses one of the test networks this tool supports.
+fn parse_network(s: &str) -> Result<Network, String> {
+    match s {
+        "regtest" => Ok(Network::Regtest),
+        "signet" => Ok(Network::Signet),
+        "testnet" => Ok(Network::Testnet),
+        _ => Err(format!(
+            "invalid network '{s}': expected one of regtest, signet, testnet"
+        )),
+    }
+}
+
+fn parse_json_rpc_version(s: &str) -> Result<JsonRpcVersion, String> {
+    match s {
+        "1.0" | "1" => Ok(JsonRpcVersion::V1),
+        "2.0" | "2" => Ok(JsonRpcVersion::V2),
+        _ => Err(format!(
+            "invalid JSON-RPC version '{s}': expected 1.0 or 2.0"
+        )),
+    }
+}
+
+// Parses an address type the way `getnewaddress` spells it.
+pub(crate) fn parse_address_type(s: &str) -> Result<AddressType, String> {
+    match s {
+        "legacy" => Ok(AddressType::Legacy),
+        "p2sh-segwit" => Ok(AddressType::P2shSegwit),
+        "bech32" => Ok(AddressType::Bech32),
+        "bech32m" => Ok(AddressType::Bech32m),
+        _ => Err(format!(
+            "invalid address type '{s}': expected one of legacy, p2sh-segwit, bech32, bech32m"
+        )),
+    }
+}
+
+// Rejects names Bitcoin Core can't use as a wallet directory or that would break the
+// `/wallet/<name>` endpoint URL.
+fn parse_wallet_name(name: &str) -> Result<String, String> {
+    if name.is_empty() {
+        return Err("wallet name must not be empty".to_owned());
+    }
+    if name == "." || name == ".." {
+        return Err(format!("invalid wallet name '{name}'"));
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| c.is_control() || matches!(c, '/' | '\\' | ':' | '?' | '#' | '%'))
+    {
+        return Err(format!(
+            "invalid wallet name '{name}': character {c:?} is not allowed"
+        ));
+    }
+    Ok(name.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::transaction::Version;
+    use bitcoin::Transaction;
+
+    #[test]
+    fn parse_psbt_round_trips() {
+        let psbt = Psbt::from_unsigned_tx(Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: Vec::new(),
+            output: Vec::new(),
+        })
+        .unwrap();
+        assert_eq!(parse_psbt(&format!("{psbt}\n")), Ok(psbt));
+    }
+
+    #[test]
+    fn parse_raw_tx_explains_what_is_wrong() {
+        assert!(parse_raw_tx("zz")
+            .unwrap_err()
+            .starts_with("invalid transaction hex"));
+        assert!(parse_raw_tx("0200")
+            .unwrap_err()
+            .starts_with("invalid raw transaction"));
+    }
+
+    #[test]
+    fn parse_psbt_rejects_bad_base64() {
+        let err = parse_psbt("not base64!").unwrap_err();
+        assert!(err.starts_with("invalid PSBT"), "{err}");
+    }
+}
@@ -0,0 +1,604 @@
+// Node connection settings and connecting to the node.
+
+use crate::backend::RpcBackend;
+use crate::error::AppError;
+use crate::rpc::{rpc_error, RPC_IN_WARMUP};
+use crate::tls::{client_config, load_ca_certs, HttpsTransport};
+use crate::trace::{ReplayTransport, RpcReplay, RpcTrace, TracingTransport};
+use bitcoincore_rpc::bitcoin::Network;
+use bitcoincore_rpc::jsonrpc::{
+    self, simple_http::SimpleHttpTransport, Request, Response, Transport,
+};
+use bitcoincore_rpc::{Auth, Client};
+use log::warn;
+use rustls::{ClientConfig, RootCertStore};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Default node access params, used when the corresponding env var is unset
+const DEFAULT_RPC_HOST: &str = "http://127.0.0.1"; // Port depends on the network
+const DEFAULT_RPC_USER: &str = "alice";
+const DEFAULT_RPC_PASS: &str = "password";
+const DEFAULT_CONNECT_ATTEMPTS: u32 = 5;
+const DEFAULT_RETRY_DELAY_MS: u64 = 500;
+const DEFAULT_WARMUP_TIMEOUT_SECS: u64 = 300;
+// Longest pause between two probes of a node that is still starting up.
+const MAX_WARMUP_DELAY: Duration = Duration::from_secs(5);
+pub const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(30);
+
+// RPC connection settings, read from BITCOIN_RPC_URL, BITCOIN_RPC_USER and BITCOIN_RPC_PASS.
+// The default URL uses the default RPC port of the selected network.
+// BITCOIN_RPC_CONNECT_ATTEMPTS and BITCOIN_RPC_RETRY_DELAY_MS tune the initial connection retry;
+// BITCOIN_RPC_WARMUP_TIMEOUT_SECS caps the wait for a node that is up but still starting.
+// A cookie file, when set, takes precedence over the user and password. Every request fails
+// after `timeout` rather than hanging on a stuck node. An https:// URL is reached over TLS,
+// trusting `ca_certs` if set and the usual web roots otherwise.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub(crate) rpc_url: String,
+    rpc_user: String,
+    rpc_pass: String,
+    cookie_file: Option<PathBuf>,
+    ca_certs: Option<Arc<RootCertStore>>,
+    timeout: Duration,
+    connect_attempts: u32,
+    retry_delay: Duration,
+    warmup_timeout: Duration,
+    json_rpc_version: JsonRpcVersion,
+    rpc_trace: Option<Arc<RpcTrace>>,
+    replay: Option<Arc<RpcReplay>>,
+}
+
+// The JSON-RPC framing of requests. Bitcoin Core answers both, but 2.0 requests get 2.0
+// replies (errors with HTTP 200, no `error: null` on success) from Core 28 on, and some proxies
+// only pass one of the two.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonRpcVersion {
+    // No `jsonrpc` member, as JSON-RPC 1.0 has none.
+    V1,
+    // `"jsonrpc": "2.0"`, which is what the RPC library sends.
+    #[default]
+    V2,
+}
+
+impl Config {
+    pub fn from_env(network: Network) -> Result<Self, String> {
+        let default_url = format!("{DEFAULT_RPC_HOST}:{}", default_rpc_port(network));
+        let rpc_url = env_or("BITCOIN_RPC_URL", &default_url);
+        let rpc_user = env_or("BITCOIN_RPC_USER", DEFAULT_RPC_USER);
+        let rpc_pass = env_or("BITCOIN_RPC_PASS", DEFAULT_RPC_PASS);
+
+        let connect_attempts = env_or(
+            "BITCOIN_RPC_CONNECT_ATTEMPTS",
+            &DEFAULT_CONNECT_ATTEMPTS.to_string(),
+        )
+        .parse::<u32>()
+        .ok()
+        .filter(|&attempts| attempts > 0)
+        .ok_or("BITCOIN_RPC_CONNECT_ATTEMPTS must be a positive integer")?;
+        let retry_delay_ms = env_or(
+            "BITCOIN_RPC_RETRY_DELAY_MS",
+            &DEFAULT_RETRY_DELAY_MS.to_string(),
+        )
+        .parse::<u64>()
+        .map_err(|_| "BITCOIN_RPC_RETRY_DELAY_MS must be a number of milliseconds")?;
+        let warmup_timeout_secs = env_or(
+            "BITCOIN_RPC_WARMUP_TIMEOUT_SECS",
+            &DEFAULT_WARMUP_TIMEOUT_SECS.to_string(),
+        )
+        .parse::<u64>()
+        .map_err(|_| "BITCOIN_RPC_WARMUP_TIMEOUT_SECS must be a number of seconds")?;
+
+        validate_rpc_url(&rpc_url)?;
+        if !rpc_user.is_empty() && rpc_pass.is_empty() {
+            return Err(format!(
+                "BITCOIN_RPC_PASS must not be empty when BITCOIN_RPC_USER is set (user '{rpc_user}')"
+            ));
+        }
+
+        Ok(Config {
+            rpc_url: rpc_url.trim_end_matches('/').to_owned(),
+            rpc_user,
+            rpc_pass,
+            cookie_file: None,
+            ca_certs: None,
+            timeout: DEFAULT_RPC_TIMEOUT,
+            connect_attempts,
+            retry_delay: Duration::from_millis(retry_delay_ms),
+            warmup_timeout: Duration::from_secs(warmup_timeout_secs),
+            json_rpc_version: JsonRpcVersion::default(),
+            rpc_trace: None,
+            replay: None,
+        })
+    }
+
+    // Authenticates with the `.cookie` file bitcoind writes to its datadir instead of the user
+    // and password. Fails unless the file can be read and holds a `user:password` pair.
+    pub fn with_cookie_file(mut self, path: &Path) -> Result<Self, String> {
+        let cookie = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read cookie file {}: {e}", path.display()))?;
+        if !cookie.trim_end().contains(':') {
+            return Err(format!(
+                "cookie file {} does not hold a user:password pair",
+                path.display()
+            ));
+        }
+        self.cookie_file = Some(path.to_owned());
+        Ok(self)
+    }
+
+    // Trusts the certificates in the PEM file at `path` instead of the usual web roots when
+    // connecting over TLS, for a proxy with a self-signed or private-CA certificate.
+    pub fn with_ca_cert(mut self, path: &Path) -> Result<Self, String> {
+        if !self.rpc_url.starts_with("https://") {
+            return Err(format!(
+                "--ca-cert needs an https:// RPC URL, but BITCOIN_RPC_URL is '{}'",
+                self.rpc_url
+            ));
+        }
+        self.ca_certs = Some(Arc::new(load_ca_certs(path)?));
+        Ok(self)
+    }
+
+    // Gives up on requests that take longer than `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // Frames every request as `version` asks.
+    pub fn with_json_rpc_version(mut self, version: JsonRpcVersion) -> Self {
+        self.json_rpc_version = version;
+        self
+    }
+
+    // Records every request of every client made from these settings, with its response, to
+    // the JSON-lines file at `path`, which is created or truncated.
+    pub fn with_rpc_trace(mut self, path: &Path) -> Result<Self, String> {
+        let trace = RpcTrace::create(path)
+            .map_err(|e| format!("cannot create RPC trace file {}: {e}", path.display()))?;
+        self.rpc_trace = Some(Arc::new(trace));
+        Ok(self)
+    }
+
+    // Answers every request from the trace at `path` (written by `with_rpc_trace`) instead of
+    // a node, so a recorded run can be repeated offline.
+    pub fn with_replay(mut self, path: &Path) -> Result<Self, String> {
+        self.replay = Some(Arc::new(RpcReplay::load(path)?));
+        Ok(self)
+    }
+
+    // The node these settings point at, to get wallet clients from.
+    pub fn node(&self) -> Node {
+        Node {
+            url: self.rpc_url.clone(),
+            auth: self.auth(),
+            tls: self
+                .rpc_url
+                .starts_with("https://")
+                .then(|| client_config(self.ca_certs.as_deref())),
+            timeout: self.timeout,
+            json_rpc_version: self.json_rpc_version,
+            trace: self.rpc_trace.clone(),
+            replay: self.replay.clone(),
+        }
+    }
+
+    pub(crate) fn auth(&self) -> Auth {
+        match &self.cookie_file {
+            Some(path) => Auth::CookieFile(path.clone()),
+            None => Auth::UserPass(self.rpc_user.clone(), self.rpc_pass.clone()),
+        }
+    }
+}
+
+// A node's RPC endpoint and credentials, which vends clients for the node itself and for its
+// wallets so the `/wallet/<name>` URLs are built in one place. Every client gets the same
+// timeout. Clients can't share a connection: a transport is bound to a single URL.
+#[derive(Debug, Clone)]
+pub struct Node {
+    url: String,
+    auth: Auth,
+    // Set for an https:// URL.
+    tls: Option<Arc<ClientConfig>>,
+    timeout: Duration,
+    json_rpc_version: JsonRpcVersion,
+    trace: Option<Arc<RpcTrace>>,
+    replay: Option<Arc<RpcReplay>>,
+}
+
+impl Node {
+    // A client for node-wide calls (`getblockchaininfo`, `createwallet`, ...). Nothing is
+    // sent until the first call.
+    pub fn base(&self) -> bitcoincore_rpc::Result<Client> {
+        self.client(&self.url)
+    }
+
+    // A client bound to the endpoint of the wallet `name`.
+    pub fn wallet(&self, name: &str) -> bitcoincore_rpc::Result<Client> {
+        self.client(&format!("{}/wallet/{name}", self.url))
+    }
+
+    // A client for `url`: answered from the replayed trace if there is one, otherwise over
+    // HTTP or HTTPS; recording its requests to the trace file if there is one.
+    fn client(&self, url: &str) -> bitcoincore_rpc::Result<Client> {
+        let client = match &self.replay {
+            Some(replay) => self.traced(
+                url,
+                ReplayTransport {
+                    target: url.to_owned(),
+                    replay: replay.clone(),
+                },
+            ),
+            None => match &self.tls {
+                Some(tls) => {
+                    let transport =
+                        HttpsTransport::new(url, self.auth.clone(), self.timeout, tls.clone())?;
+                    self.traced(url, transport)
+                }
+                None => self.traced(url, http_transport(url, self.auth.clone(), self.timeout)?),
+            },
+        };
+        Ok(Client::from_jsonrpc(client))
+    }
+
+    fn traced<T: Transport>(&self, url: &str, transport: T) -> jsonrpc::Client {
+        let transport = FramedTransport {
+            inner: transport,
+            version: self.json_rpc_version,
+        };
+        match &self.trace {
+            Some(trace) => jsonrpc::Client::with_transport(TracingTransport {
+                inner: transport,
+                target: url.to_owned(),
+                trace: trace.clone(),
+            }),
+            None => jsonrpc::Client::with_transport(transport),
+        }
+    }
+}
+
+// Connects to the node `config` points at, retrying as configured.
+pub fn connect(config: &Config) -> Result<Client, AppError> {
+    connect_with_retry(
+        &config.node(),
+        config.connect_attempts,
+        config.retry_delay,
+        config.warmup_timeout,
+    )
+}
+
+// Connects to `node` and probes it with `getblockchaininfo`, retrying up to
+// `attempts` times. The delay starts at `base_delay` and doubles after every failure.
+// A node that answers but is still starting up (loading its block index, ...) is polled
+// separately, without using up attempts, for up to `warmup_timeout`.
+fn connect_with_retry(
+    node: &Node,
+    attempts: u32,
+    base_delay: Duration,
+    warmup_timeout: Duration,
+) -> Result<Client, AppError> {
+    let url = &node.url;
+    let mut delay = base_delay;
+    let mut attempt = 1;
+    let mut warmup_delay = base_delay.min(MAX_WARMUP_DELAY);
+    let mut warming_up_since = None;
+    loop {
+        let result = node
+            .base()
+            .and_then(|client| client.get_blockchain_info().map(|_| client));
+        let e = match result {
+            Ok(client) => return Ok(client),
+            Err(e) => e,
+        };
+        if let Some(message) = warmup_message(&e) {
+            let waited = warming_up_since.get_or_insert_with(Instant::now).elapsed();
+            if waited >= warmup_timeout {
+                return Err(AppError::NodeNotReady(format!(
+                    "still starting up ({message}) after {}s; set \
+                     BITCOIN_RPC_WARMUP_TIMEOUT_SECS to wait longer",
+                    warmup_timeout.as_secs()
+                )));
+            }
+            let pause = warmup_delay.min(warmup_timeout - waited);
+            warn!("The node at {url} is still starting up ({message}); retrying in {pause:?}");
+            thread::sleep(pause);
+            warmup_delay = warmup_delay.saturating_mul(2).min(MAX_WARMUP_DELAY);
+            continue;
+        }
+        if attempt >= attempts {
+            return Err(e.into());
+        }
+        warn!(
+            "Connecting to {url} failed (attempt {attempt}/{attempts}): {e}; retrying in {delay:?}"
+        );
+        thread::sleep(delay);
+        delay = delay.saturating_mul(2);
+        attempt += 1;
+    }
+}
+
+// What the node is busy with ("Loading block index...", ...) if `err` says it is still starting
+// up and not answering RPCs yet.
+fn warmup_message(err: &bitcoincore_rpc::Error) -> Option<&str> {
+    rpc_error(err)
+        .filter(|rpc_err| rpc_err.code == RPC_IN_WARMUP)
+        .map(|rpc_err| rpc_err.message.as_str())
+}
+
+// The transport `Client::new` would use, but one that gives up on a request after `timeout`,
+// which `Client::new` doesn't let us set.
+fn http_transport(
+    url: &str,
+    auth: Auth,
+    timeout: Duration,
+) -> bitcoincore_rpc::Result<SimpleHttpTransport> {
+    let (user, pass) = auth.get_user_pass()?;
+    let mut builder = SimpleHttpTransport::builder()
+        .url(url)
+        .map_err(|e| bitcoincore_rpc::Error::JsonRpc(e.into()))?
+        .timeout(timeout);
+    if let Some(user) = user {
+        builder = builder.auth(user, pass);
+    }
+    Ok(builder.build())
+}
+
+// Passes requests on to `inner` with the `jsonrpc` member `version` calls for.
+struct FramedTransport<T> {
+    inner: T,
+    version: JsonRpcVersion,
+}
+
+impl<T> FramedTransport<T> {
+    fn frame<'a>(&self, request: Request<'a>) -> Request<'a> {
+        match self.version {
+            JsonRpcVersion::V1 => Request {
+                jsonrpc: None,
+                ..request
+            },
+            JsonRpcVersion::V2 => Request {
+                jsonrpc: Some("2.0"),
+                ..request
+            },
+        }
+    }
+}
+
+impl<T: Transport> Transport for FramedTransport<T> {
+    fn send_request(&self, request: Request) -> Result<Response, jsonrpc::Error> {
+        self.inner.send_request(self.frame(request))
+    }
+
+    fn send_batch(&self, requests: &[Request]) -> Result<Vec<Response>, jsonrpc::Error> {
+        let requests: Vec<_> = requests
+            .iter()
+            .map(|request| self.frame(request.clone()))
+            .collect();
+        self.inner.send_batch(&requests)
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.fmt_target(f)
+    }
+}
+
+// Bitcoin Core's default RPC port for `network`.
+fn default_rpc_port(network: Network) -> u16 {
+    match network {
+        Network::Testnet => 18332,
+        Network::Signet => 38332,
+        Network::Regtest => 18443,
+        _ => 8332,
+    }
+}
+
+// Where bitcoind writes its auth cookie for `network` when run with the platform's default
+// datadir, if the home directory is known.
+pub fn default_cookie_path(network: Network) -> Option<PathBuf> {
+    let datadir = if cfg!(windows) {
+        PathBuf::from(env::var_os("APPDATA")?).join("Bitcoin")
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(env::var_os("HOME")?).join("Library/Application Support/Bitcoin")
+    } else {
+        PathBuf::from(env::var_os("HOME")?).join(".bitcoin")
+    };
+    let network_dir = match network {
+        Network::Testnet => "testnet3",
+        Network::Signet => "signet",
+        Network::Regtest => "regtest",
+        _ => "",
+    };
+    Some(datadir.join(network_dir).join(".cookie"))
+}
+
+// Returns the value of `key`, or `default` when it is unset or empty.
+fn env_or(key: &str, default: &str) -> String {
+    match env::var(key) {
+        Ok(value) if !value.is_empty() => value,
+        _ => default.to_owned(),
+    }
+}
+
+// Checks that `url` looks like `http(s)://host[:port][/path]`.
+fn validate_rpc_url(url: &str) -> Result<(), String> {
+    let rest = url
+        .strip_prefix("http://")
+        .or_else(|| url.strip_prefix("https://"))
+        .ok_or_else(|| {
+            format!("invalid RPC URL '{url}': expected an http:// or https:// scheme")
+        })?;
+    let authority = rest.split('/').next().unwrap_or_default();
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (authority, None),
+    };
+    if host.is_empty() {
+        return Err(format!("invalid RPC URL '{url}': missing host"));
+    }
+    if let Some(port) = port {
+        port.parse::<u16>()
+            .map_err(|_| format!("invalid RPC URL '{url}': bad port '{port}'"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+
+    // Keeps the `jsonrpc` member of every request it is sent.
+    #[derive(Clone, Default)]
+    struct Framing(Arc<Mutex<Vec<Option<String>>>>);
+
+    impl Transport for Framing {
+        fn send_request(&self, request: Request) -> Result<Response, jsonrpc::Error> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(request.jsonrpc.map(str::to_owned));
+            Err(jsonrpc::Error::Transport("not sent".into()))
+        }
+
+        fn send_batch(&self, requests: &[Request]) -> Result<Vec<Response>, jsonrpc::Error> {
+            for request in requests {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(request.jsonrpc.map(str::to_owned));
+            }
+            Err(jsonrpc::Error::Transport("not sent".into()))
+        }
+
+        fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "framing")
+        }
+    }
+
+    // A node at `name`'s own trace file that answers `getnetworkinfo` with `networkinfo` in
+    // turn (the last one repeating) and is synced otherwise.
+    fn replayed_node(name: &str, networkinfo: &[Value]) -> Node {
+        let line = |method: &str, response: &Value| {
+            json!({
+                "target": "http://127.0.0.1:18443",
+                "method": method,
+                "params": [],
+                "response": response,
+            })
+            .to_string()
+        };
+        let mut lines: Vec<String> = networkinfo
+            .iter()
+            .map(|response| line("getnetworkinfo", response))
+            .collect();
+        lines.push(line(
+            "getblockchaininfo",
+            &json!({"result": {
+                "chain": "regtest", "blocks": 0, "headers": 0,
+                "bestblockhash": "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206",
+                "difficulty": 0.0, "mediantime": 0, "verificationprogress": 1.0,
+                "initialblockdownload": false, "chainwork": "00", "size_on_disk": 0,
+                "pruned": false, "warnings": "",
+            }, "error": null, "id": 1}),
+        ));
+        let path = env::temp_dir().join(format!("rust-warmup-{}-{name}.jsonl", std::process::id()));
+        fs::write(&path, lines.join("\n")).unwrap();
+        let replay = RpcReplay::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        Node {
+            url: "http://127.0.0.1:18443".to_owned(),
+            auth: Auth::None,
+            tls: None,
+            timeout: DEFAULT_RPC_TIMEOUT,
+            json_rpc_version: JsonRpcVersion::V2,
+            trace: None,
+            replay: Some(Arc::new(replay)),
+        }
+    }
+
+    fn warming_up() -> Value {
+        json!({"result": null, "error": {"code": -28, "message": "Loading block index…"}, "id": 1})
+    }
+
+    fn network_info() -> Value {
+        json!({"result": {
+            "version": 270000, "subversion": "/Satoshi:27.0.0/", "protocolversion": 70016,
+            "localservices": "0000000000000c09", "localrelay": true, "timeoffset": 0,
+            "connections": 0, "networkactive": true, "networks": [], "relayfee": 0.00001,
+            "incrementalfee": 0.00001, "localaddresses": [], "warnings": "",
+        }, "error": null, "id": 1})
+    }
+
+    #[test]
+    fn warmup_is_waited_out_without_using_up_attempts() {
+        let node = replayed_node("warm", &[warming_up(), warming_up(), network_info()]);
+        let connected =
+            connect_with_retry(&node, 1, Duration::from_millis(1), Duration::from_secs(60));
+        assert!(connected.is_ok(), "{:?}", connected.err());
+    }
+
+    #[test]
+    fn warmup_wait_is_capped() {
+        let node = replayed_node("cold", &[warming_up()]);
+        let err = connect_with_retry(
+            &node,
+            5,
+            Duration::from_millis(1),
+            Duration::from_millis(20),
+        )
+        .unwrap_err();
+        assert!(
+            matches!(&err, AppError::NodeNotReady(message) if message.contains("Loading block index")),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn ca_certs_need_an_https_url() {
+        assert!(validate_rpc_url("https://node.example.com/bitcoin").is_ok());
+        let ca = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/tls/ca.pem");
+        let config = Config::from_env(Network::Regtest).unwrap();
+        let err = config.clone().with_ca_cert(&ca).unwrap_err();
+        assert!(
+            err.starts_with("--ca-cert needs an https:// RPC URL"),
+            "{err}"
+        );
+        let config = Config {
+            rpc_url: "https://localhost:8443".to_owned(),
+            ..config
+        };
+        let node = config.with_ca_cert(&ca).unwrap().node();
+        assert!(node.tls.is_some());
+        assert!(node.base().is_ok());
+    }
+
+    #[test]
+    fn requests_are_framed_as_configured() {
+        let request = Request {
+            method: "getblockcount",
+            params: &[],
+            id: json!(1),
+            jsonrpc: Some("2.0"),
+        };
+        let sent = Framing::default();
+        for version in [JsonRpcVersion::V1, JsonRpcVersion::V2] {
+            let transport = FramedTransport {
+                inner: sent.clone(),
+                version,
+            };
+            let _ = transport.send_request(request.clone());
+            let _ = transport.send_batch(std::slice::from_ref(&request));
+        }
+        assert_eq!(
+            *sent.0.lock().unwrap(),
+            [None, None, Some("2.0".to_owned()), Some("2.0".to_owned())]
+        );
+    }
+}
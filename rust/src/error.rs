@@ -0,0 +1,308 @@
+// The errors a run can fail with.
+
+use crate::tx::CONFIRMATION_TIMEOUT;
+use bitcoincore_rpc::bitcoin::{Amount, BlockHash, Network, SignedAmount, Txid};
+use std::fmt;
+use std::io;
+
+// Errors that can abort a run.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    // A call to the node failed.
+    #[error("RPC error: {0}")]
+    Rpc(#[from] bitcoincore_rpc::Error),
+    // A call to the node failed during `step`, e.g. "mining to address X".
+    #[error("RPC error while {step}: {source}")]
+    RpcFailed {
+        step: String,
+        source: bitcoincore_rpc::Error,
+    },
+    // Reading or writing a local file failed.
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    // Invalid command-line arguments or environment configuration.
+    #[error("configuration error: {0}")]
+    Config(String),
+    // An address from the node belongs to a different network than the selected one.
+    #[error("address {address} is not valid on {expected}")]
+    WrongNetwork { address: String, expected: Network },
+    // The transaction has no confirming block yet.
+    #[error("transaction {0} is not confirmed in any block yet")]
+    TransactionUnconfirmed(Txid),
+    // A transaction input refers to an output that doesn't exist.
+    #[error("output {txid}:{vout} does not exist")]
+    OutputNotFound { txid: Txid, vout: u32 },
+    // A transaction input has no previous output because it belongs to a coinbase.
+    #[error("transaction {0} is a coinbase and spends no previous outputs")]
+    UnexpectedCoinbase(Txid),
+    // The outputs of a transaction add up to more than its inputs.
+    #[error("outputs of {txid} exceed its inputs by {excess}; cannot compute the fee")]
+    NegativeFee { txid: Txid, excess: Amount },
+    // The transaction vanished from the wallet or a conflicting one was confirmed instead.
+    #[error("transaction {0} was dropped or replaced before it confirmed")]
+    TransactionDropped(Txid),
+    // The transaction didn't reach the wanted number of confirmations in time.
+    #[error(
+        "transaction {txid} has only {confirmations} of {target} confirmations after {:?}",
+        CONFIRMATION_TIMEOUT
+    )]
+    ConfirmationTimeout {
+        txid: Txid,
+        confirmations: u32,
+        target: u32,
+    },
+    // The wallet couldn't sign every input of a transaction it built.
+    #[error("the wallet could not fully sign the transaction: {0}")]
+    IncompleteSignature(String),
+    // `importdescriptors` rejected a descriptor.
+    #[error("could not import descriptor {descriptor}: {reason}")]
+    DescriptorImport { descriptor: String, reason: String },
+    // The sending wallet can't cover the requested amount.
+    #[error(
+        "insufficient funds: need {need} but only {have} is spendable (short by {})",
+        shortfall(*.have, *.need)
+    )]
+    InsufficientFunds { have: Amount, need: Amount },
+    // The transaction is already in a block, so it can no longer be bumped or abandoned.
+    #[error(
+        "transaction {txid} already has {confirmations} confirmation(s); only unconfirmed \
+         transactions can be bumped or abandoned"
+    )]
+    TransactionConfirmed { txid: Txid, confirmations: u32 },
+    // The transaction left the mempool because conflicting transactions replaced it.
+    #[error("transaction {txid} was replaced by {}", join_txids(.by))]
+    TransactionReplaced { txid: Txid, by: Vec<Txid> },
+    // A wallet's balance didn't change the way the send should have changed it.
+    #[error("balance of wallet '{wallet}' changed by {actual} instead of {expected}")]
+    BalanceMismatch {
+        wallet: String,
+        expected: SignedAmount,
+        actual: SignedAmount,
+    },
+    // The block at `height` in the active chain isn't the one the report names, e.g. because
+    // a reorg replaced it.
+    #[error(
+        "block {height} of the active chain is {actual}, not {expected}; the chain was reorganized"
+    )]
+    ReorgDetected {
+        height: u64,
+        expected: BlockHash,
+        actual: BlockHash,
+    },
+    // An output of the send is below the dust threshold for its script type.
+    #[error(
+        "the {output} of {amount} is below the dust threshold of {threshold}; pass --allow-dust \
+         to send it anyway"
+    )]
+    DustOutput {
+        output: String,
+        amount: Amount,
+        threshold: Amount,
+    },
+    // The run would have to mine blocks on a network where this tool can't (see
+    // mining::ensure_can_mine).
+    #[error("mining is not supported on {network} without a miner: {reason}")]
+    MiningUnsupported {
+        network: Network,
+        reason: &'static str,
+    },
+    // A transaction an input spends can't be looked up: it isn't the wallet's and the node
+    // keeps no transaction index.
+    #[error(
+        "txindex required for input resolution: transaction {txid} is not in the wallet and the \
+         node has no transaction index; {}",
+        if *.pruned {
+            "a pruned node can't keep one, so resync it without -prune and with -txindex=1"
+        } else {
+            "restart bitcoind with -txindex=1"
+        }
+    )]
+    TxindexRequired { txid: Txid, pruned: bool },
+    // The node answers but isn't ready for the flow: still starting up or still syncing.
+    #[error("node is not ready: {0}")]
+    NodeNotReady(String),
+    // Ctrl-C stopped the run between blocks.
+    #[error("interrupted by the user after mining {blocks_mined} block(s)")]
+    Interrupted { blocks_mined: usize },
+    // Mining to a height the chain has already passed.
+    #[error("the chain is already at height {height}, past the target height {target}")]
+    ChainPastHeight { height: u64, target: u64 },
+    // A line of out.txt can't be parsed.
+    #[error("malformed out.txt at line {line} ({field}): {reason}")]
+    MalformedReport {
+        line: usize,
+        field: &'static str,
+        reason: String,
+    },
+    // A line of out.txt doesn't match what the node reports.
+    #[error("selftest failed at out.txt line {line} ({field}): {reason}")]
+    SelftestFailed {
+        line: usize,
+        field: &'static str,
+        reason: String,
+    },
+}
+
+// Process exit codes, listed in the `--help` text (see cli::EXIT_CODES_HELP). Failures that
+// fit no other category use EXIT_RPC, the code every failure used to exit with.
+pub const EXIT_RPC: i32 = 1;
+pub const EXIT_INSUFFICIENT_FUNDS: i32 = 2;
+pub const EXIT_CONFIG: i32 = 3;
+pub const EXIT_VERIFICATION: i32 = 4;
+// 128 + SIGINT, what a shell reports for a command Ctrl-C killed.
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+impl AppError {
+    // The process exit code for this error, so scripts can tell a failing node from a wallet
+    // that can't pay, a misconfigured run, and a result that didn't check out.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AppError::InsufficientFunds { .. } => EXIT_INSUFFICIENT_FUNDS,
+            AppError::Config(_)
+            | AppError::WrongNetwork { .. }
+            | AppError::ChainPastHeight { .. }
+            | AppError::DustOutput { .. }
+            | AppError::MiningUnsupported { .. }
+            | AppError::TxindexRequired { .. } => EXIT_CONFIG,
+            AppError::SelftestFailed { .. }
+            | AppError::ReorgDetected { .. }
+            | AppError::BalanceMismatch { .. } => EXIT_VERIFICATION,
+            AppError::Interrupted { .. } => EXIT_INTERRUPTED,
+            _ => EXIT_RPC,
+        }
+    }
+}
+
+// How much `have` falls short of `need`.
+fn shortfall(have: Amount, need: Amount) -> Amount {
+    need.checked_sub(have).unwrap_or(Amount::ZERO)
+}
+
+// `txids` as a comma-separated list.
+fn join_txids(txids: &[Txid]) -> String {
+    txids
+        .iter()
+        .map(Txid::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Names the step an RPC call was part of, so a failure says what the run was doing.
+pub(crate) trait RpcContext<T> {
+    fn context<D: fmt::Display>(self, step: impl FnOnce() -> D) -> Result<T, AppError>;
+}
+
+impl<T> RpcContext<T> for bitcoincore_rpc::Result<T> {
+    fn context<D: fmt::Display>(self, step: impl FnOnce() -> D) -> Result<T, AppError> {
+        self.map_err(|source| AppError::RpcFailed {
+            step: step().to_string(),
+            source,
+        })
+    }
+}
+
+// Steps that mine blocks fail with AppError already (see AppError::Interrupted); only their
+// RPC failures get the step named.
+impl<T> RpcContext<T> for Result<T, AppError> {
+    fn context<D: fmt::Display>(self, step: impl FnOnce() -> D) -> Result<T, AppError> {
+        self.map_err(|e| match e {
+            AppError::Rpc(source) => AppError::RpcFailed {
+                step: step().to_string(),
+                source,
+            },
+            e => e,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_include_computed_parts() {
+        let err = AppError::InsufficientFunds {
+            have: Amount::from_int_btc(5),
+            need: Amount::from_int_btc(20),
+        };
+        assert_eq!(
+            err.to_string(),
+            "insufficient funds: need 20 BTC but only 5 BTC is spendable (short by 15 BTC)"
+        );
+        let txid: Txid = "b2f8f0b1e9ac2f4a0ad0ac4b7d6c1ea4f1f5a3b9c8d7e6f5a4b3c2d1e0f9a8b7"
+            .parse()
+            .unwrap();
+        let err = AppError::TransactionReplaced {
+            txid,
+            by: vec![txid, txid],
+        };
+        assert_eq!(
+            err.to_string(),
+            format!("transaction {txid} was replaced by {txid}, {txid}")
+        );
+        let err = AppError::TxindexRequired { txid, pruned: true };
+        assert!(err
+            .to_string()
+            .starts_with("txindex required for input resolution"));
+        assert!(err
+            .to_string()
+            .ends_with("resync it without -prune and with -txindex=1"));
+    }
+
+    #[test]
+    fn rpc_context_names_the_step() {
+        let result: bitcoincore_rpc::Result<()> = Err(bitcoincore_rpc::Error::UnexpectedStructure);
+        let err = result.context(|| "querying the chain").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("RPC error while querying the chain: "));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[test]
+    fn exit_codes_follow_the_failure_category() {
+        assert_eq!(
+            AppError::Rpc(bitcoincore_rpc::Error::UnexpectedStructure).exit_code(),
+            1
+        );
+        let funds = AppError::InsufficientFunds {
+            have: Amount::ZERO,
+            need: Amount::ONE_BTC,
+        };
+        assert_eq!(funds.exit_code(), 2);
+        assert_eq!(AppError::Config("bad".to_owned()).exit_code(), 3);
+        let past = AppError::ChainPastHeight {
+            height: 300,
+            target: 200,
+        };
+        assert_eq!(past.exit_code(), 3);
+        let mining = AppError::MiningUnsupported {
+            network: Network::Signet,
+            reason: "",
+        };
+        assert_eq!(mining.exit_code(), 3);
+        let selftest = AppError::SelftestFailed {
+            line: 1,
+            field: "txid",
+            reason: String::new(),
+        };
+        assert_eq!(selftest.exit_code(), 4);
+        let interrupted = AppError::Interrupted { blocks_mined: 42 };
+        assert_eq!(
+            interrupted.to_string(),
+            "interrupted by the user after mining 42 block(s)"
+        );
+        assert_eq!(interrupted.exit_code(), 130);
+    }
+
+    #[test]
+    fn rpc_context_keeps_other_errors() {
+        let result: Result<(), AppError> = Err(AppError::Interrupted { blocks_mined: 3 });
+        let err = result.context(|| "mining").unwrap_err();
+        assert!(matches!(err, AppError::Interrupted { blocks_mined: 3 }));
+        let result: Result<(), AppError> =
+            Err(AppError::Rpc(bitcoincore_rpc::Error::UnexpectedStructure));
+        let err = result.context(|| "mining").unwrap_err();
+        assert!(err.to_string().starts_with("RPC error while mining: "));
+    }
+}
@@ -0,0 +1,41 @@
+// Stopping cleanly on Ctrl-C. The first Ctrl-C only raises a flag, which the mining and
+// waiting loops check between blocks, so a run stops after the block it is mining instead of
+// in the middle of an RPC call; a second one quits at once.
+
+use crate::error::{AppError, EXIT_INTERRUPTED};
+use log::warn;
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Blocks mined by this process, for the message an interrupted run exits with.
+static BLOCKS_MINED: AtomicUsize = AtomicUsize::new(0);
+
+// Installs the Ctrl-C handler. Without one, Ctrl-C kills the process as it always did.
+pub fn install_ctrlc_handler() {
+    let installed = ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            process::exit(EXIT_INTERRUPTED);
+        }
+        warn!("Interrupted; stopping after the current block (press Ctrl-C again to quit now).");
+    });
+    if let Err(e) = installed {
+        warn!("Could not install the Ctrl-C handler: {e}");
+    }
+}
+
+// Counts `count` freshly mined blocks.
+pub(crate) fn record_blocks_mined(count: usize) {
+    BLOCKS_MINED.fetch_add(count, Ordering::SeqCst);
+}
+
+// Fails with AppError::Interrupted once Ctrl-C was pressed. Loops call it before each block.
+pub(crate) fn check() -> Result<(), AppError> {
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        return Err(AppError::Interrupted {
+            blocks_mined: BLOCKS_MINED.load(Ordering::SeqCst),
+        });
+    }
+    Ok(())
+}
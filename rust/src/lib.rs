@@ -0,0 +1,514 @@
+pub mod backend;
+mod bench;
+pub mod cli;
+pub mod config;
+pub mod error;
+mod interrupt;
+pub mod mining;
+pub mod out;
+pub mod report;
+mod rpc;
+mod tls;
+mod trace;
+pub mod tx;
+pub mod wallet;
+
+pub use backend::RpcBackend;
+pub use cli::{
+    BlockRef, Cli, Command, GlobalArgs, OutputArgs, OutputFormat, RunAllArgs, SendArgs, SetupArgs,
+    WalletOptions, WalletRole,
+};
+pub use config::{connect, default_cookie_path, Config, JsonRpcVersion, Node};
+pub use error::AppError;
+pub use interrupt::install_ctrlc_handler;
+pub use mining::{
+    generate_empty_block, mine_to_height, mine_to_maturity, run_setup, MiningSummary,
+    MIN_SPENDABLE_BALANCE,
+};
+pub use out::{
+    format_amount, read_report, report_json, write_address_type, write_fee_rate, write_report,
+    write_summary, write_tx_hex, Unit,
+};
+pub use report::{build_report, verify_height_hash, Payment, TraderOutput, TxReport};
+pub use tx::{
+    abandon, bump_fee, compute_fee, fee_for_tx, follow_replacements, preview_send,
+    run_analyze_psbt, run_preview, run_send, SendOutcome, SendPreview,
+};
+pub use wallet::{
+    addresses_by_label, balance_breakdown, list_utxos, load_or_create_wallet, open_wallets,
+    print_addresses_by_label, print_transaction_table, print_utxo_table, received_by_label,
+    recent_transactions, BalanceBreakdown, BalanceDelta, WalletState, Wallets,
+};
+
+use bench::run_bench;
+use bitcoincore_rpc::bitcoin::address::NetworkUnchecked;
+use bitcoincore_rpc::bitcoin::{Address, Amount, Network, Txid};
+use bitcoincore_rpc::json::GetBlockchainInfoResult;
+use cli::{parse_psbt, parse_raw_tx};
+use error::RpcContext;
+use log::{debug, info, warn};
+use mining::{ensure_can_mine, inspect_coinbase, COINBASE_MATURITY};
+use report::{run_report, verify_out_txt, write_decoded_tx};
+use rpc::{get_index_info, utxo_set_summary};
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use wallet::{check_addr, RECEIVED_LABEL};
+
+// Logs the state-changing RPC calls a real run would make, using only read-only calls to
+// find out which of them are needed.
+fn print_plan(
+    rpc: &impl RpcBackend,
+    global: &GlobalArgs,
+    args: &RunAllArgs,
+) -> Result<(), AppError> {
+    let loaded = rpc.list_wallets()?;
+    let on_disk = rpc.list_wallet_dir()?;
+    for wallet_name in [&global.miner_wallet, &global.trader_wallet] {
+        if loaded.contains(wallet_name) {
+            info!("[dry-run] Wallet '{wallet_name}' is already loaded; nothing to do.");
+        } else if on_disk.contains(wallet_name) {
+            info!("[dry-run] Would call loadwallet \"{wallet_name}\".");
+        } else {
+            info!("[dry-run] Would call createwallet \"{wallet_name}\".");
+        }
+    }
+    let mine_call = if args.send.use_generateblock {
+        "generateblock once per block"
+    } else {
+        "generatetoaddress once"
+    };
+    info!(
+        "[dry-run] Would call {mine_call} for {} blocks on a new \"Mining Reward\" address of \
+         '{}', then mine one block per call until its balance is positive.",
+        COINBASE_MATURITY + 1,
+        global.miner_wallet
+    );
+    let send_calls = if args.send.manual_tx {
+        "createrawtransaction, fundrawtransaction, signrawtransactionwithwallet and \
+         sendrawtransaction"
+    } else if args.send.psbt {
+        "walletcreatefundedpsbt, walletprocesspsbt, finalizepsbt and sendrawtransaction"
+    } else if args.send.to.len() > 1 {
+        "sendmany"
+    } else {
+        "sendtoaddress"
+    };
+    if args.send.to.is_empty() {
+        info!(
+            "[dry-run] Would call {send_calls} for {} BTC from '{}' to a new \"Received\" \
+             address of '{}'.",
+            args.send.send_amount.to_btc(),
+            global.miner_wallet,
+            global.trader_wallet
+        );
+    } else {
+        let recipients = args
+            .send
+            .to
+            .iter()
+            .map(|r| {
+                let address = check_addr(r.address.clone(), global.network)?;
+                Ok(format!("{} BTC to {address}", r.amount.to_btc()))
+            })
+            .collect::<Result<Vec<_>, AppError>>()?
+            .join(", ");
+        info!(
+            "[dry-run] Would call {send_calls} from '{}' paying {recipients}.",
+            global.miner_wallet
+        );
+    }
+    info!(
+        "[dry-run] Would call {mine_call} for {} blocks to confirm the transaction.",
+        args.send.confirmations
+    );
+    info!(
+        "[dry-run] Would write the transaction details to {}.",
+        args.output.out.display()
+    );
+    Ok(())
+}
+
+// The transaction described by an out.txt left at `output.out` by an earlier run, with the
+// addresses it pays, if the Miner wallet still sees it confirmed. Anything else (no file, a
+// file that doesn't parse, an unknown or unconfirmed txid) means there is nothing to reuse.
+fn previous_send(
+    wallets: &Wallets<impl RpcBackend>,
+    network: Network,
+    output: &OutputArgs,
+) -> Result<Option<(Txid, Vec<Address>)>, AppError> {
+    let out_path = env::current_dir()?.join(&output.out);
+    let Ok(file) = File::open(&out_path) else {
+        return Ok(None);
+    };
+    let previous = match read_report(BufReader::new(file), output.amount_unit) {
+        Ok(previous) => previous,
+        Err(e) => {
+            debug!("Not reusing {}: {e}", out_path.display());
+            return Ok(None);
+        }
+    };
+    match wallets.miner.get_transaction(&previous.txid, None) {
+        Ok(tx_info) if tx_info.info.confirmations > 0 => {}
+        Ok(_) => {
+            debug!("Not reusing {}: it is no longer confirmed", previous.txid);
+            return Ok(None);
+        }
+        Err(e) => {
+            debug!("Not reusing {}: {e}", previous.txid);
+            return Ok(None);
+        }
+    }
+    let trader_addresses = previous
+        .trader_output_address
+        .split(',')
+        .map(|address| {
+            address
+                .parse::<Address<NetworkUnchecked>>()
+                .ok()?
+                .require_network(network)
+                .ok()
+        })
+        .collect::<Option<Vec<_>>>();
+    Ok(trader_addresses.map(|addresses| (previous.txid, addresses)))
+}
+
+// Setup, send and report in one go. Unless `force`, a run whose out.txt already describes a
+// confirmed transaction skips setup and send and only writes the report again.
+fn run_all(
+    rpc: &impl RpcBackend,
+    wallets: &Wallets<impl RpcBackend>,
+    network: Network,
+    send: &SendArgs,
+    output: &OutputArgs,
+    force: bool,
+) -> Result<(), AppError> {
+    if !force {
+        if let Some((txid, trader_addresses)) = previous_send(wallets, network, output)? {
+            info!(
+                "{} already describes {txid}, which is confirmed; reporting it again instead of \
+                 sending (pass --force to send anyway).",
+                output.out.display()
+            );
+            return run_report(
+                wallets,
+                network,
+                &txid,
+                TraderOutput::Addresses(&trader_addresses),
+                None,
+                output,
+                false,
+            );
+        }
+    }
+    let mining_address = run_setup(
+        wallets,
+        network,
+        send.address_type,
+        MIN_SPENDABLE_BALANCE,
+        send.use_generateblock,
+    )?;
+    let sent = run_send(rpc, wallets, network, send, &mining_address)?;
+    run_report(
+        wallets,
+        network,
+        &sent.txid,
+        TraderOutput::Addresses(&sent.trader_addresses),
+        Some((send, sent.balance_delta)),
+        output,
+        false,
+    )
+}
+
+// Prints the chain, height and sync state of the node, and fails unless it is out of initial
+// block download.
+fn healthcheck(info: &GetBlockchainInfoResult) -> Result<(), AppError> {
+    println!("Chain:                  {}", info.chain);
+    println!("Blocks:                 {}", info.blocks);
+    println!("Headers:                {}", info.headers);
+    println!(
+        "Initial block download: {}",
+        if info.initial_block_download {
+            "yes"
+        } else {
+            "no"
+        }
+    );
+    if info.initial_block_download {
+        return Err(AppError::NodeNotReady(format!(
+            "in initial block download at block {} of {} ({:.2}% verified)",
+            info.blocks,
+            info.headers,
+            info.verification_progress * 100.0
+        )));
+    }
+    Ok(())
+}
+
+// Runs what `cli` asks for against the node configured in the environment.
+pub fn run(cli: Cli) -> Result<(), AppError> {
+    let global = &cli.global;
+    global.validate().map_err(AppError::Config)?;
+    let mut config = Config::from_env(global.network)
+        .map_err(AppError::Config)?
+        .with_timeout(global.timeout_secs)
+        .with_json_rpc_version(global.json_rpc_version);
+    if let Some(replay) = &global.replay {
+        config = config.with_replay(replay).map_err(AppError::Config)?;
+    }
+    if let Some(trace) = &global.rpc_trace {
+        config = config.with_rpc_trace(trace).map_err(AppError::Config)?;
+    }
+    if let Some(ca_cert) = &global.ca_cert {
+        config = config.with_ca_cert(ca_cert).map_err(AppError::Config)?;
+    }
+    if let Some(cookie) = &global.cookie {
+        config = config.with_cookie_file(cookie).map_err(AppError::Config)?;
+    } else if global.auto {
+        match default_cookie_path(global.network).filter(|path| path.is_file()) {
+            Some(cookie) => {
+                config = config.with_cookie_file(&cookie).map_err(AppError::Config)?;
+                info!("Authenticating with the cookie file {}", cookie.display());
+            }
+            None => info!("No bitcoind cookie file found; authenticating with user and password"),
+        }
+    }
+
+    // Connect to Bitcoin Core RPC, waiting for the node if it isn't up yet
+    let rpc = match connect(&config) {
+        // A benchmark without a node has nothing to measure, which isn't a failure.
+        Err(e) if matches!(cli.command, Some(Command::Bench(_))) => {
+            warn!("No node to benchmark against ({e}); skipping the benchmark.");
+            return Ok(());
+        }
+        rpc => rpc?,
+    };
+    let node = config.node();
+
+    // Get blockchain info
+    let blockchain_info = rpc.get_blockchain_info().context(|| "querying the chain")?;
+    debug!("Blockchain Info: {blockchain_info:?}");
+    if blockchain_info.chain != global.network {
+        return Err(AppError::Config(format!(
+            "the node at {} runs {} but --network is {}",
+            config.rpc_url, blockchain_info.chain, global.network
+        )));
+    }
+    // `getindexinfo` only exists on Core 0.21+, so this is informational only.
+    if let Ok(indexes) = get_index_info(&rpc) {
+        for (name, index) in &indexes {
+            debug!(
+                "Index {name}: synced {}, best block height {}",
+                index.synced, index.best_block_height
+            );
+        }
+    }
+
+    if global.info {
+        let utxo_set = utxo_set_summary(&rpc).context(|| "summarizing the UTXO set")?;
+        println!("Chain:        {}", blockchain_info.chain);
+        println!("Height:       {}", utxo_set.height);
+        println!("Best block:   {}", utxo_set.best_block);
+        println!("UTXOs:        {}", utxo_set.txouts);
+        println!("Total amount: {:.8} BTC", utxo_set.total_amount.to_btc());
+        return Ok(());
+    }
+
+    match cli.command.unwrap_or(Command::RunAll(cli.run_all)) {
+        Command::Setup(args) => {
+            let wallets = open_wallets(&rpc, &node, global)?;
+            if let Some(target_height) = args.to_height {
+                ensure_can_mine(global.network)?;
+                let mining_address = check_addr(
+                    wallets
+                        .miner
+                        .get_new_address(Some("Mining Reward"), Some(args.address_type))
+                        .context(|| "generating the Miner's mining address")?,
+                    global.network,
+                )?;
+                mine_to_height(
+                    &wallets.miner,
+                    target_height,
+                    &mining_address,
+                    args.use_generateblock,
+                )?;
+                return Ok(());
+            }
+            run_setup(
+                &wallets,
+                global.network,
+                args.address_type,
+                args.min_balance.unwrap_or(MIN_SPENDABLE_BALANCE),
+                args.use_generateblock,
+            )?;
+        }
+        Command::Send(args) => {
+            let wallets = open_wallets(&rpc, &node, global)?;
+            if args.preview {
+                return run_preview(&rpc, &wallets, global.network, &args);
+            }
+            // Confirmation blocks need somewhere to go; the Miner keeps the rewards.
+            let mining_address = check_addr(
+                wallets
+                    .miner
+                    .get_new_address(Some("Mining Reward"), Some(args.address_type))
+                    .context(|| "generating the Miner's mining address")?,
+                global.network,
+            )?;
+            let sent = run_send(&rpc, &wallets, global.network, &args, &mining_address)?;
+            println!("{}", sent.txid);
+        }
+        Command::InspectCoinbase(args) => inspect_coinbase(&rpc, args.block, global.network)?,
+        Command::Utxos => {
+            let wallets = open_wallets(&rpc, &node, global)?;
+            let utxos =
+                list_utxos(&wallets.miner).context(|| "listing the Miner's unspent outputs")?;
+            print_utxo_table(&utxos);
+        }
+        Command::Transactions(args) => {
+            let wallets = open_wallets(&rpc, &node, global)?;
+            let (name, wallet) = match args.wallet {
+                WalletRole::Miner => ("Miner", &wallets.miner),
+                WalletRole::Trader => ("Trader", &wallets.trader),
+            };
+            let txs = recent_transactions(wallet, args.count, args.skip)
+                .context(|| format!("listing the {name} wallet's transactions"))?;
+            print_transaction_table(&txs);
+        }
+        Command::Received(args) => {
+            let wallets = open_wallets(&rpc, &node, global)?;
+            let received = received_by_label(&wallets.trader, RECEIVED_LABEL, args.min_conf)?;
+            if received == Amount::ZERO {
+                println!(
+                    "The trader has received nothing under the label '{RECEIVED_LABEL}' with \
+                     {} or more confirmations.",
+                    args.min_conf
+                );
+            } else {
+                println!(
+                    "{:.8} BTC received under the label '{RECEIVED_LABEL}' ({} or more \
+                     confirmations)",
+                    received.to_btc(),
+                    args.min_conf
+                );
+            }
+        }
+        Command::ListAddresses => {
+            let wallets = open_wallets(&rpc, &node, global)?;
+            print_addresses_by_label(&wallets, global.network)?;
+        }
+        Command::BumpFee(args) => {
+            let wallets = open_wallets(&rpc, &node, global)?;
+            println!("{}", bump_fee(&wallets.miner, &args.txid)?);
+        }
+        Command::Abandon(args) => {
+            let wallets = open_wallets(&rpc, &node, global)?;
+            abandon(&wallets.miner, &args.txid)?;
+        }
+        Command::AnalyzePsbt(args) => {
+            let psbt = match args.psbt.as_deref() {
+                Some(psbt) if psbt != "-" => psbt.to_owned(),
+                _ => io::read_to_string(io::stdin())?,
+            };
+            run_analyze_psbt(&rpc, &parse_psbt(&psbt).map_err(AppError::Config)?)?;
+        }
+        Command::Report(args) => {
+            let wallets = open_wallets(&rpc, &node, global)?;
+            let trader_address = args
+                .trader_address
+                .map(|a| check_addr(a, global.network))
+                .transpose()?;
+            let trader = match &trader_address {
+                Some(trader_address) => {
+                    TraderOutput::Addresses(std::slice::from_ref(trader_address))
+                }
+                None => TraderOutput::OwnedBy(&wallets.trader),
+            };
+            // A fee-bumped transaction is reported through its latest replacement.
+            let txid = follow_replacements(&wallets.miner, &args.txid)?;
+            run_report(
+                &wallets,
+                global.network,
+                &txid,
+                trader,
+                None,
+                &args.output,
+                args.verify,
+            )?;
+        }
+        Command::RunAll(args) => {
+            if args.dry_run {
+                return print_plan(&rpc, global, &args);
+            }
+            let wallets = open_wallets(&rpc, &node, global)?;
+            if args.send.preview {
+                return run_preview(&rpc, &wallets, global.network, &args.send);
+            }
+            run_all(
+                &rpc,
+                &wallets,
+                global.network,
+                &args.send,
+                &args.output,
+                args.force,
+            )?;
+        }
+        Command::Bench(args) => {
+            let wallets = open_wallets(&rpc, &node, global)?;
+            run_bench(&rpc, &wallets, global.network, &args)?;
+        }
+        Command::Healthcheck => healthcheck(&blockchain_info)?,
+        Command::Decode(args) => {
+            let hex = match args.hex.as_deref() {
+                Some(hex) if hex != "-" => hex.to_owned(),
+                _ => io::read_to_string(io::stdin())?,
+            };
+            let tx = parse_raw_tx(&hex).map_err(AppError::Config)?;
+            let wallets = open_wallets(&rpc, &node, global)?;
+            write_decoded_tx(
+                &mut io::stdout(),
+                &wallets.miner,
+                TraderOutput::OwnedBy(&wallets.trader),
+                &tx,
+                global.network,
+            )?;
+        }
+        Command::Selftest(args) => {
+            if !args.send.to.is_empty() {
+                return Err(AppError::Config(
+                    "selftest checks that the trader output belongs to the trader wallet, so it \
+                     can't be combined with --to"
+                        .to_owned(),
+                ));
+            }
+            if args.send.preview {
+                return Err(AppError::Config(
+                    "selftest has to send; use --preview with send or run-all".to_owned(),
+                ));
+            }
+            let wallets = open_wallets(&rpc, &node, global)?;
+            let output = OutputArgs {
+                out: args.out,
+                format: OutputFormat::Text,
+                // verify_out_txt reads the amounts back as BTC.
+                amount_unit: Unit::Btc,
+                emit_hex: false,
+                no_summary: false,
+                // verify_out_txt expects exactly the 10 lines.
+                emit_feerate: false,
+                emit_address_type: false,
+            };
+            // Always send: the point is to check a fresh transaction end to end.
+            run_all(&rpc, &wallets, global.network, &args.send, &output, true)?;
+            let out_path = env::current_dir()?.join(&output.out);
+            verify_out_txt(&rpc, &wallets, global.network, &out_path)?;
+            println!(
+                "selftest passed: every line of {} checks out",
+                out_path.display()
+            );
+        }
+    }
+
+    Ok(())
+}
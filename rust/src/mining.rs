@@ -0,0 +1,451 @@
+// Mining blocks to a wallet and looking at what they paid out.
+
+use crate::backend::RpcBackend;
+use crate::cli::BlockRef;
+use crate::error::{AppError, RpcContext};
+use crate::interrupt;
+use crate::rpc::rpc_call;
+use crate::wallet::{balance_breakdown, check_addr, owns, Wallets};
+use bitcoin::hex::DisplayHex;
+use bitcoincore_rpc::bitcoin::{Address, Amount, BlockHash, Network, Transaction, Txid};
+use bitcoincore_rpc::json::AddressType;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, info, log_enabled, warn, Level};
+use serde::Deserialize;
+use serde_json::json;
+use std::io::{self, IsTerminal};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+// Number of confirmations a coinbase output needs before it can be spent (consensus rule).
+pub(crate) const COINBASE_MATURITY: u64 = 100;
+
+// Fails unless this tool can mine blocks on `network`, before anything is sent or mined. How
+// blocks come about on each network:
+// - regtest: `generatetoaddress`, or `generateblock` with --use-generateblock.
+// - signet: a block is only valid with a signature satisfying the network's challenge script,
+//   which neither RPC adds, so `generatetoaddress` would keep failing or hang. Blocks come from
+//   a signet miner (Bitcoin Core's contrib/signet/miner) holding the challenge key; `send
+//   --watch` waits for them.
+// - testnet: blocks need real proof of work, which the node's CPU miner won't find in time;
+//   other miners produce them, so use `send --watch` there too.
+pub(crate) fn ensure_can_mine(network: Network) -> Result<(), AppError> {
+    let reason = match network {
+        Network::Regtest => return Ok(()),
+        Network::Signet => {
+            "signet blocks must be signed for the network's challenge; run a signet miner \
+             (contrib/signet/miner) and confirm with send --watch"
+        }
+        _ => {
+            "blocks need real proof of work; fund the miner wallet from a faucet and confirm \
+             with send --watch"
+        }
+    };
+    Err(AppError::MiningUnsupported { network, reason })
+}
+
+// Mines one block to `addr` with `generateblock`. Besides the coinbase it holds exactly
+// `txids`, which must be in the mempool, whatever else the mempool contains.
+pub(crate) fn generate_block(
+    wallet: &impl RpcBackend,
+    addr: &Address,
+    txids: &[Txid],
+) -> bitcoincore_rpc::Result<BlockHash> {
+    #[derive(Deserialize)]
+    struct GenerateBlockResult {
+        hash: BlockHash,
+    }
+    let block: GenerateBlockResult = rpc_call(
+        wallet,
+        "generateblock",
+        &[json!(addr.to_string()), json!(txids)],
+    )?;
+    interrupt::record_blocks_mined(1);
+    Ok(block.hash)
+}
+
+// Mines one block to `addr` that holds nothing but its coinbase.
+pub fn generate_empty_block(
+    wallet: &impl RpcBackend,
+    addr: &Address,
+) -> bitcoincore_rpc::Result<BlockHash> {
+    generate_block(wallet, addr, &[])
+}
+
+// Mines `count` blocks to `addr`, with `generatetoaddress` or, with `use_generateblock`, one
+// empty block per `generateblock` call. Ctrl-C stops it before the next call.
+pub(crate) fn generate_blocks(
+    wallet: &impl RpcBackend,
+    count: u64,
+    addr: &Address,
+    use_generateblock: bool,
+) -> Result<Vec<BlockHash>, AppError> {
+    if !use_generateblock {
+        interrupt::check()?;
+        let block_hashes = wallet.generate_to_address(count, addr)?;
+        interrupt::record_blocks_mined(block_hashes.len());
+        return Ok(block_hashes);
+    }
+    (0..count)
+        .map(|_| {
+            interrupt::check()?;
+            Ok(generate_empty_block(wallet, addr)?)
+        })
+        .collect()
+}
+
+// Mines COINBASE_MATURITY + 1 blocks to `addr` (in a single call unless `use_generateblock`),
+// which leaves the reward of the first one spendable. Returns the hashes of the blocks mined.
+pub fn mine_to_maturity(
+    wallet: &impl RpcBackend,
+    addr: &Address,
+    use_generateblock: bool,
+) -> Result<Vec<BlockHash>, AppError> {
+    // One call gives no progress to report, so just show that something is happening.
+    let spinner = if show_progress() {
+        ProgressBar::new_spinner()
+    } else {
+        ProgressBar::hidden()
+    };
+    spinner.set_message(format!("Mining {} blocks...", COINBASE_MATURITY + 1));
+    spinner.enable_steady_tick(PROGRESS_TICK);
+    let block_hashes = generate_blocks(wallet, COINBASE_MATURITY + 1, addr, use_generateblock);
+    spinner.finish_and_clear();
+    block_hashes
+}
+
+// Mines to `addr` exactly as many blocks as it takes to bring the chain from its current
+// height to `target_height` (in a single call unless `use_generateblock`), and returns their
+// hashes. Mines nothing if the chain is already there.
+pub fn mine_to_height(
+    wallet: &impl RpcBackend,
+    target_height: u64,
+    addr: &Address,
+    use_generateblock: bool,
+) -> Result<Vec<BlockHash>, AppError> {
+    let height = wallet
+        .get_block_count()
+        .context(|| "reading the chain height")?;
+    let count = target_height
+        .checked_sub(height)
+        .ok_or(AppError::ChainPastHeight {
+            height,
+            target: target_height,
+        })?;
+    info!("Mining {count} block(s) from height {height} to {target_height}");
+    generate_blocks(wallet, count, addr, use_generateblock)
+        .context(|| format!("mining to height {target_height}"))
+}
+
+// How often a spinner redraws while waiting on the node.
+const PROGRESS_TICK: Duration = Duration::from_millis(100);
+
+// Progress bars are drawn only for an interactive user: with info logging on (no `--quiet`)
+// and stdout a terminal.
+fn show_progress() -> bool {
+    log_enabled!(Level::Info) && io::stdout().is_terminal()
+}
+
+// Subsidy of the first blocks, before any halving.
+const INITIAL_SUBSIDY: Amount = Amount::from_int_btc(50);
+
+// Number of blocks between subsidy halvings: regtest halves every 150 blocks so that halvings
+// are easy to reach.
+fn subsidy_halving_interval(network: Network) -> u64 {
+    match network {
+        Network::Regtest => 150,
+        _ => 210_000,
+    }
+}
+
+// New coins a block at `height` may create, not counting fees.
+pub fn block_subsidy(height: u64, network: Network) -> Amount {
+    let halvings = height / subsidy_halving_interval(network);
+    // The subsidy is shifted down to nothing after 64 halvings.
+    match INITIAL_SUBSIDY.to_sat().checked_shr(halvings as u32) {
+        Some(sats) if halvings < 64 => Amount::from_sat(sats),
+        _ => Amount::ZERO,
+    }
+}
+
+// Returns the coinbase (first) transaction of the block `hash`. Passing the block hash lets
+// `getrawtransaction` find it without a transaction index.
+fn coinbase_of_block(
+    rpc: &impl RpcBackend,
+    hash: &BlockHash,
+) -> bitcoincore_rpc::Result<Transaction> {
+    let block = rpc.get_block_info(hash)?;
+    let coinbase_txid = block
+        .tx
+        .first()
+        .ok_or(bitcoincore_rpc::Error::UnexpectedStructure)?;
+    rpc.get_raw_transaction(coinbase_txid, Some(hash))
+}
+
+// Prints what the coinbase of `block` paid and to whom.
+pub(crate) fn inspect_coinbase(
+    rpc: &impl RpcBackend,
+    block: BlockRef,
+    network: Network,
+) -> Result<(), AppError> {
+    let hash = match block {
+        BlockRef::Height(height) => rpc
+            .get_block_hash(height)
+            .context(|| format!("looking up the block at height {height}"))?,
+        BlockRef::Hash(hash) => hash,
+    };
+    let height = rpc
+        .get_block_info(&hash)
+        .context(|| format!("fetching block {hash}"))?
+        .height as u64;
+    let coinbase =
+        coinbase_of_block(rpc, &hash).context(|| format!("fetching the coinbase of {hash}"))?;
+    let reward = coinbase.output.iter().map(|out| out.value).sum::<Amount>();
+    let subsidy = block_subsidy(height, network);
+
+    println!("Block:        {hash} (height {height})");
+    println!("Coinbase:     {}", coinbase.txid());
+    println!(
+        "Reward:       {:.8} BTC (subsidy {:.8} BTC, fees {:.8} BTC)",
+        reward.to_btc(),
+        subsidy.to_btc(),
+        reward.checked_sub(subsidy).unwrap_or(Amount::ZERO).to_btc()
+    );
+    // BIP34 puts the height first in the scriptSig; whatever the miner pushed after it
+    // (extranonce, tags) follows.
+    if let Some(input) = coinbase.input.first() {
+        let mut pushes = input.script_sig.instructions();
+        if let Some(Ok(height_push)) = pushes.next() {
+            let height_push = height_push.script_num().map(|n| n.to_string());
+            println!("Height push:  {}", height_push.unwrap_or_default());
+        }
+        let extranonce: Vec<u8> = pushes
+            .filter_map(Result::ok)
+            .filter_map(|push| push.push_bytes().map(|bytes| bytes.as_bytes().to_vec()))
+            .flatten()
+            .collect();
+        println!("Extranonce:   {}", extranonce.to_lower_hex_string());
+    }
+    for (n, out) in coinbase.output.iter().enumerate() {
+        match Address::from_script(&out.script_pubkey, network) {
+            Ok(addr) => println!("Output #{n}:    {:.8} BTC to {addr}", out.value.to_btc()),
+            // E.g. the OP_RETURN segwit witness commitment.
+            Err(_) => println!(
+                "Output #{n}:    {:.8} BTC to script {}",
+                out.value.to_btc(),
+                out.script_pubkey.as_bytes().to_lower_hex_string()
+            ),
+        }
+    }
+    Ok(())
+}
+
+// Sum of the coinbase outputs of `block_hashes`: subsidies plus fees collected by whoever
+// mined them.
+fn total_mined_reward(
+    wallet: &impl RpcBackend,
+    block_hashes: &[BlockHash],
+) -> bitcoincore_rpc::Result<Amount> {
+    let mut reward = Amount::ZERO;
+    for hash in block_hashes {
+        let block = wallet.get_block(hash)?;
+        if let Some(coinbase) = block.txdata.first() {
+            reward += coinbase.output.iter().map(|out| out.value).sum::<Amount>();
+        }
+    }
+    Ok(reward)
+}
+
+// Sum of the coinbase outputs of the blocks at `heights` that pay addresses of `wallet`: the
+// subsidies plus fees it earned by mining them, whoever asked for the blocks.
+pub(crate) fn coinbase_rewards(
+    wallet: &impl RpcBackend,
+    heights: RangeInclusive<u64>,
+    network: Network,
+) -> bitcoincore_rpc::Result<Amount> {
+    let mut rewards = Amount::ZERO;
+    for height in heights {
+        let block = wallet.get_block(&wallet.get_block_hash(height)?)?;
+        let Some(coinbase) = block.txdata.first() else {
+            continue;
+        };
+        for out in &coinbase.output {
+            let Ok(addr) = Address::from_script(&out.script_pubkey, network) else {
+                continue;
+            };
+            if owns(wallet, &addr) {
+                rewards += out.value;
+            }
+        }
+    }
+    Ok(rewards)
+}
+
+// Outcome of a mining phase.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MiningSummary {
+    // Address the block rewards were paid to.
+    pub address: Address,
+    // Blocks mined, in order.
+    pub block_hashes: Vec<BlockHash>,
+    // Spendable balance of the mining wallet once done.
+    pub final_balance: Amount,
+}
+
+// The smallest positive balance: what setup mines for unless asked for more.
+pub const MIN_SPENDABLE_BALANCE: Amount = Amount::from_sat(1);
+
+// Mines COINBASE_MATURITY + 1 blocks to `addr` in one go, then keeps mining one block at a
+// time should that not be enough to give `wallet` a spendable balance of `target` (e.g. the
+// node uses a different maturity rule, or `target` is more than one block reward).
+pub fn mine_spendable_balance(
+    wallet: &impl RpcBackend,
+    addr: &Address,
+    target: Amount,
+    use_generateblock: bool,
+) -> Result<MiningSummary, AppError> {
+    let mut block_hashes = mine_to_maturity(wallet, addr, use_generateblock)?;
+    let mut summary = mine_until_balance(wallet, addr, target, use_generateblock)?;
+    if !summary.block_hashes.is_empty() {
+        warn!(
+            "Balance was still below {target} after {} blocks; mined {} more.",
+            block_hashes.len(),
+            summary.block_hashes.len()
+        );
+    }
+    block_hashes.append(&mut summary.block_hashes);
+    summary.block_hashes = block_hashes;
+    Ok(summary)
+}
+
+// Mines blocks to `addr` one at a time until `wallet` has a spendable balance of at least
+// `target`. Mines nothing if the balance is already there.
+pub fn mine_until_balance(
+    wallet: &impl RpcBackend,
+    addr: &Address,
+    target: Amount,
+    use_generateblock: bool,
+) -> Result<MiningSummary, AppError> {
+    let mut balances = balance_breakdown(wallet)?;
+    let mut block_hashes = Vec::new();
+    // The bar fills up as the balance approaches `target`.
+    let bar = if show_progress() && balances.trusted < target {
+        ProgressBar::new(target.to_sat())
+    } else {
+        ProgressBar::hidden()
+    };
+    bar.set_style(
+        ProgressStyle::with_template("{bar:40} {msg}")
+            .expect("valid progress template")
+            .progress_chars("=> "),
+    );
+    while balances.trusted < target {
+        block_hashes.extend(generate_blocks(wallet, 1, addr, use_generateblock)?);
+        balances = balance_breakdown(wallet)?;
+        bar.set_position(balances.trusted.to_sat());
+        // Rewards stay immature for COINBASE_MATURITY blocks, which is why mining more
+        // doesn't raise the spendable balance right away.
+        let progress = format!(
+            "{} block(s) mined: {} BTC spendable, {} BTC immature (target {target})",
+            block_hashes.len(),
+            balances.trusted.to_btc(),
+            balances.immature.to_btc()
+        );
+        debug!("{progress}");
+        bar.set_message(progress);
+    }
+    bar.finish_and_clear();
+    Ok(MiningSummary {
+        address: addr.clone(),
+        block_hashes,
+        final_balance: balances.trusted,
+    })
+}
+
+// Generates a spendable balance of at least `target` in the Miner wallet and returns the
+// address it mined to.
+pub fn run_setup(
+    wallets: &Wallets<impl RpcBackend>,
+    network: Network,
+    address_type: AddressType,
+    target: Amount,
+    use_generateblock: bool,
+) -> Result<Address, AppError> {
+    ensure_can_mine(network)?;
+    // How many blocks needs to be mined?
+    // 1. Generate a mining address with label "Mining Reward"
+    let mining_address = check_addr(
+        wallets
+            .miner
+            .get_new_address(Some("Mining Reward"), Some(address_type))
+            .context(|| "generating the Miner's mining address")?,
+        network,
+    )?;
+    info!("Miner's mining address: {mining_address}");
+
+    // 2. Mine blocks to this address until the wallet has a positive balance (or `target`)
+    // Coinbase rewards require COINBASE_MATURITY (100) confirmations to mature before they are
+    // spendable, so mining COINBASE_MATURITY + 1 blocks makes the first reward spendable.
+    let mining = mine_spendable_balance(&wallets.miner, &mining_address, target, use_generateblock)
+        .context(|| format!("mining to address {mining_address}"))?;
+    info!(
+        "Blocks mined until a balance of at least {target}: {}",
+        mining.block_hashes.len()
+    );
+    // ---
+    // Coinbase rewards (mining rewards) require 100 confirmations before they can be spent. This is a consensus rule to prevent chain reorganizations from invalidating recent coinbase spends. That's why the wallet balance is not immediately available after mining a block; you must mine 100 more blocks before the reward is mature and spendable.
+    // ---
+    let balances = balance_breakdown(&wallets.miner)
+        .context(|| "reading the Miner's balances after mining")?;
+    info!(
+        "Miner wallet balance: {} BTC spendable, {} BTC immature",
+        balances.trusted.to_btc(),
+        balances.immature.to_btc()
+    );
+
+    // 3. Sum up what the mined blocks paid out. Only the first rewards are mature yet.
+    let reward = total_mined_reward(&wallets.miner, &mining.block_hashes)
+        .context(|| "adding up the rewards of the mined blocks")?;
+    let height = wallets.miner.get_block_count()?;
+    let halving_interval = subsidy_halving_interval(network);
+    info!(
+        "Coinbase rewards of the {} mined block(s): {} BTC. The subsidy is now {} BTC and \
+         halves every {halving_interval} blocks (next at height {}).",
+        mining.block_hashes.len(),
+        reward.to_btc(),
+        block_subsidy(height, network).to_btc(),
+        (height / halving_interval + 1) * halving_interval
+    );
+    Ok(mining_address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_regtest_can_be_mined() {
+        assert!(ensure_can_mine(Network::Regtest).is_ok());
+        let signet = ensure_can_mine(Network::Signet).unwrap_err();
+        assert!(signet
+            .to_string()
+            .starts_with("mining is not supported on signet"));
+        assert!(signet.to_string().contains("contrib/signet/miner"));
+    }
+
+    #[test]
+    fn subsidy_halves_every_interval() {
+        let regtest = |height| block_subsidy(height, Network::Regtest);
+        assert_eq!(regtest(0), Amount::from_int_btc(50));
+        assert_eq!(regtest(149), Amount::from_int_btc(50));
+        assert_eq!(regtest(150), Amount::from_int_btc(25));
+        assert_eq!(regtest(300), Amount::from_sat(1_250_000_000));
+        assert_eq!(
+            block_subsidy(210_000, Network::Bitcoin),
+            Amount::from_sat(2_500_000_000)
+        );
+        // Past 64 halvings the shift would wrap around instead of reaching zero.
+        assert_eq!(regtest(64 * 150), Amount::ZERO);
+        assert_eq!(block_subsidy(u64::MAX, Network::Bitcoin), Amount::ZERO);
+    }
+}
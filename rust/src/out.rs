@@ -0,0 +1,525 @@
+// The out.txt format: one attribute of the transaction per line, amounts in BTC with 8
+// decimal places or in satoshis. Also the optional tx.hex.
+
+use crate::cli::parse_address_type;
+use crate::error::AppError;
+use crate::report::{Payment, TxReport};
+use bitcoincore_rpc::bitcoin::consensus::encode::serialize;
+use bitcoincore_rpc::bitcoin::hex::DisplayHex;
+use bitcoincore_rpc::bitcoin::{Amount, Denomination, Transaction, Witness};
+use bitcoincore_rpc::json::AddressType;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+// The lines of out.txt, in order.
+pub const OUT_TXT_FIELDS: [&str; 10] = [
+    "txid",
+    "miner_input_address",
+    "miner_input_amount",
+    "trader_output_address",
+    "trader_output_amount",
+    "miner_change_address",
+    "miner_change_amount",
+    "fee",
+    "block_height",
+    "block_hash",
+];
+
+// Unit the amounts of a report are written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Unit {
+    // BTC with 8 decimal places, e.g. `20.00000000`.
+    #[default]
+    Btc,
+    // Whole satoshis, e.g. `2000000000`.
+    Sat,
+}
+
+impl FromStr for Unit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "btc" => Ok(Unit::Btc),
+            "sat" => Ok(Unit::Sat),
+            _ => Err(format!(
+                "invalid amount unit '{s}': expected one of btc, sat"
+            )),
+        }
+    }
+}
+
+// Formats `amount` the way every monetary line of out.txt spells it in `unit`.
+pub fn format_amount(amount: Amount, unit: Unit) -> String {
+    match unit {
+        Unit::Btc => format!("{:.8}", amount.to_btc()),
+        Unit::Sat => amount.to_sat().to_string(),
+    }
+}
+
+// Parses an amount written by `format_amount` in `unit`.
+fn parse_amount(s: &str, unit: Unit) -> Result<Amount, String> {
+    let denomination = match unit {
+        Unit::Btc => Denomination::Bitcoin,
+        Unit::Sat => Denomination::Satoshi,
+    };
+    Amount::from_str_in(s, denomination).map_err(|e| match unit {
+        Unit::Btc => format!("'{s}' is not a BTC amount: {e}"),
+        Unit::Sat => format!("'{s}' is not a satoshi amount: {e}"),
+    })
+}
+
+// Writes `report` in the out.txt format, with amounts in `unit`.
+pub fn write_report(w: &mut impl Write, report: &TxReport, unit: Unit) -> io::Result<()> {
+    writeln!(w, "{}", report.txid)?;
+    writeln!(w, "{}", report.miner_input_address)?;
+    writeln!(w, "{}", format_amount(report.miner_input_amount, unit))?;
+    writeln!(w, "{}", report.trader_output_address)?;
+    writeln!(w, "{}", format_amount(report.trader_output_amount, unit))?;
+    writeln!(w, "{}", report.miner_change_address)?;
+    writeln!(w, "{}", format_amount(report.miner_change_amount, unit))?;
+    writeln!(w, "{}", format_amount(report.fee, unit))?;
+    writeln!(w, "{}", report.block_height)?;
+    writeln!(w, "{}", report.block_hash)
+}
+
+// `report` as JSON, with amounts in `unit`: BTC amounts are numbers with up to 8 decimal
+// places (as the node reports them), satoshi amounts integers.
+pub fn report_json(report: &TxReport, unit: Unit) -> serde_json::Result<Value> {
+    let mut value = serde_json::to_value(report)?;
+    if unit == Unit::Sat {
+        value["miner_input_amount"] = json!(report.miner_input_amount.to_sat());
+        value["trader_output_amount"] = json!(report.trader_output_amount.to_sat());
+        value["miner_change_amount"] = json!(report.miner_change_amount.to_sat());
+        value["fee"] = json!(report.fee.to_sat());
+        for (n, payment) in report.recipients.iter().enumerate() {
+            value["recipients"][n]["amount"] = json!(payment.amount.to_sat());
+        }
+        if let Some(delta) = &report.balance_delta {
+            value["balance_delta"]["miner"] = json!(delta.miner.to_sat());
+            value["balance_delta"]["trader"] = json!(delta.trader.to_sat());
+        }
+    }
+    if let Some(fee_rate) = report.fee_rate_sat_per_vb() {
+        value["fee_rate_sat_per_vb"] = json!(fee_rate);
+    }
+    Ok(value)
+}
+
+// Writes the optional 11th line of out.txt (`--emit-feerate`): the fee rate of `report` in
+// sat/vB with one decimal place, or nothing if its size isn't known.
+pub fn write_fee_rate(w: &mut impl Write, report: &TxReport) -> io::Result<()> {
+    match report.vsize.filter(|&vsize| vsize > 0) {
+        Some(vsize) => writeln!(w, "{:.1}", report.fee.to_sat() as f64 / vsize as f64),
+        None => writeln!(w),
+    }
+}
+
+// The `getnewaddress` name of `address_type`, as `--address-type` takes it.
+pub(crate) fn address_type_name(address_type: AddressType) -> &'static str {
+    match address_type {
+        AddressType::Legacy => "legacy",
+        AddressType::P2shSegwit => "p2sh-segwit",
+        AddressType::Bech32 => "bech32",
+        AddressType::Bech32m => "bech32m",
+    }
+}
+
+// Writes the optional out.txt line of `--emit-address-type`: the type of the Miner's change
+// address, or nothing if there is no change.
+pub fn write_address_type(w: &mut impl Write, report: &TxReport) -> io::Result<()> {
+    writeln!(
+        w,
+        "{}",
+        report
+            .address_type
+            .map(address_type_name)
+            .unwrap_or_default()
+    )
+}
+
+// Parses a report written by `write_report` with amounts in `unit`. The file has the 10 lines of
+// OUT_TXT_FIELDS, plus the fee rate line of `write_fee_rate` and the address type line of
+// `write_address_type` if they were asked for, so what out.txt leaves out is left empty: the
+// recipients are only known when a single trader address is paid, and there is no size or
+// balance delta.
+pub fn read_report(r: impl BufRead, unit: Unit) -> Result<TxReport, AppError> {
+    let mut lines = r.lines().collect::<io::Result<Vec<String>>>()?;
+    let mut address_type = None;
+    if (OUT_TXT_FIELDS.len() + 1..=OUT_TXT_FIELDS.len() + 2).contains(&lines.len()) {
+        for (n, extra) in lines.drain(OUT_TXT_FIELDS.len()..).enumerate() {
+            // Either optional line may be empty, and either may come without the other.
+            if extra.is_empty() || extra.parse::<f64>().is_ok() {
+                continue;
+            }
+            address_type =
+                Some(
+                    parse_address_type(&extra).map_err(|_| AppError::MalformedReport {
+                        line: OUT_TXT_FIELDS.len() + n + 1,
+                        field: "fee_rate or address_type",
+                        reason: format!("'{extra}' is neither a fee rate nor an address type"),
+                    })?,
+                );
+        }
+    }
+    if lines.len() != OUT_TXT_FIELDS.len() {
+        return Err(AppError::MalformedReport {
+            line: lines.len().min(OUT_TXT_FIELDS.len()) + 1,
+            field: "file",
+            reason: format!(
+                "expected {} lines, found {}",
+                OUT_TXT_FIELDS.len(),
+                lines.len()
+            ),
+        });
+    }
+    let malformed = |index: usize, reason: String| AppError::MalformedReport {
+        line: index + 1,
+        field: OUT_TXT_FIELDS[index],
+        reason,
+    };
+    let amount = |index: usize| parse_amount(&lines[index], unit).map_err(|e| malformed(index, e));
+
+    let txid = lines[0]
+        .parse()
+        .map_err(|e| malformed(0, format!("'{}' is not a txid: {e}", lines[0])))?;
+    let trader_output_address = lines[3].clone();
+    let trader_output_amount = amount(4)?;
+    let recipients = if trader_output_address.is_empty() || trader_output_address.contains(',') {
+        Vec::new()
+    } else {
+        vec![Payment {
+            address: trader_output_address.clone(),
+            amount: trader_output_amount,
+        }]
+    };
+    Ok(TxReport {
+        txid,
+        miner_input_address: lines[1].clone(),
+        miner_input_amount: amount(2)?,
+        trader_output_address,
+        trader_output_amount,
+        recipients,
+        miner_change_address: lines[5].clone(),
+        miner_change_amount: amount(6)?,
+        address_type,
+        fee: amount(7)?,
+        vsize: None,
+        weight: None,
+        block_height: lines[8]
+            .parse()
+            .map_err(|e| malformed(8, format!("'{}' is not a height: {e}", lines[8])))?,
+        block_hash: lines[9]
+            .parse()
+            .map_err(|e| malformed(9, format!("'{}' is not a block hash: {e}", lines[9])))?,
+        balance_delta: None,
+    })
+}
+
+// Writes the tx.hex that `--emit-hex` puts next to out.txt: `tx` serialized with its witness
+// data (what `getrawtransaction` returns) on the first line, and without it (the form its
+// txid hashes) on the second. The lines are equal for a transaction without witnesses.
+pub fn write_tx_hex(w: &mut impl Write, tx: &Transaction) -> io::Result<()> {
+    let mut stripped = tx.clone();
+    for input in &mut stripped.input {
+        input.witness = Witness::new();
+    }
+    writeln!(w, "{}", serialize(tx).to_lower_hex_string())?;
+    writeln!(w, "{}", serialize(&stripped).to_lower_hex_string())
+}
+
+// Writes `rows` as left-aligned columns, each as wide as its widest cell, two spaces apart.
+pub(crate) fn write_columns(w: &mut impl Write, rows: &[Vec<String>]) -> io::Result<()> {
+    let mut widths = Vec::new();
+    for row in rows {
+        widths.resize(widths.len().max(row.len()), 0);
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in rows {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(w, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+// Writes the table a successful run ends with: the txid, confirming block height, fee,
+// trader amount and miner change of `report`, side by side, with the amounts in `unit`. When
+// the size of the transaction is known, a line with its fee rate, vsize and weight follows.
+pub fn write_summary(w: &mut impl Write, report: &TxReport, unit: Unit) -> io::Result<()> {
+    let unit_name = match unit {
+        Unit::Btc => "BTC",
+        Unit::Sat => "sat",
+    };
+    write_columns(
+        w,
+        &[
+            vec![
+                "TXID".to_owned(),
+                "HEIGHT".to_owned(),
+                format!("FEE ({unit_name})"),
+                format!("TRADER ({unit_name})"),
+                format!("CHANGE ({unit_name})"),
+            ],
+            vec![
+                report.txid.to_string(),
+                report.block_height.to_string(),
+                format_amount(report.fee, unit),
+                format_amount(report.trader_output_amount, unit),
+                format_amount(report.miner_change_amount, unit),
+            ],
+        ],
+    )?;
+    if let (Some(fee_rate), Some(vsize), Some(weight)) =
+        (report.fee_rate_sat_per_vb(), report.vsize, report.weight)
+    {
+        writeln!(
+            w,
+            "Fee rate: {fee_rate:.3} sat/vB ({vsize} vB, {weight} WU)"
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_report() -> TxReport {
+        let trader = "bcrt1qq2yshcmzdlznnpxx258xswqlmqcxjs4dssfxt2".to_owned();
+        TxReport {
+            txid: "b2f8f0b1e9ac2f4a0ad0ac4b7d6c1ea4f1f5a3b9c8d7e6f5a4b3c2d1e0f9a8b7"
+                .parse()
+                .unwrap(),
+            miner_input_address: "bcrt1p8wpt9v4frpf3tkn0srd97pksgsxc5hs52lafxwru9kgeephvs7rqjeprhg"
+                .to_owned(),
+            miner_input_amount: Amount::from_int_btc(50),
+            trader_output_address: trader.clone(),
+            trader_output_amount: Amount::from_int_btc(20),
+            recipients: vec![Payment {
+                address: trader,
+                amount: Amount::from_int_btc(20),
+            }],
+            miner_change_address:
+                "bcrt1pz9e3wxuxkkqzvhx3q2ryn2jrh5mc6f3eh0e0ktgmcfx8p0xfcdhqejz3mt".to_owned(),
+            miner_change_amount: Amount::from_sat(2_999_998_590),
+            address_type: None,
+            fee: Amount::from_sat(1410),
+            vsize: None,
+            weight: None,
+            block_height: 102,
+            block_hash: "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206"
+                .parse()
+                .unwrap(),
+            balance_delta: None,
+        }
+    }
+
+    fn round_trip(report: &TxReport, unit: Unit) -> TxReport {
+        let mut out = Vec::new();
+        write_report(&mut out, report, unit).unwrap();
+        read_report(out.as_slice(), unit).unwrap()
+    }
+
+    #[test]
+    fn write_then_read_is_identity() {
+        let report = sample_report();
+        assert_eq!(round_trip(&report, Unit::Btc), report);
+        assert_eq!(round_trip(&report, Unit::Sat), report);
+    }
+
+    #[test]
+    fn write_then_read_keeps_several_addresses_and_no_change() {
+        let mut report = sample_report();
+        report.miner_input_address = format!(
+            "{},{}",
+            report.miner_input_address, report.miner_change_address
+        );
+        report.trader_output_address = format!("{},{}", report.trader_output_address, "x");
+        report.recipients.clear();
+        report.miner_change_address.clear();
+        report.miner_change_amount = Amount::ZERO;
+        assert_eq!(round_trip(&report, Unit::Btc), report);
+    }
+
+    #[test]
+    fn written_report_has_ten_lines() {
+        let mut out = Vec::new();
+        write_report(&mut out, &sample_report(), Unit::Btc).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.lines().count(), OUT_TXT_FIELDS.len());
+        assert_eq!(text.lines().nth(4), Some("20.00000000"));
+    }
+
+    #[test]
+    fn summary_lines_up_headers_and_values() {
+        let mut out = Vec::new();
+        write_summary(&mut out, &sample_report(), Unit::Sat).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("TXID  "));
+        for header in ["HEIGHT", "FEE (sat)", "TRADER (sat)", "CHANGE (sat)"] {
+            let column = lines[0].find(header).unwrap();
+            assert_ne!(lines[1].as_bytes()[column], b' ');
+            assert_eq!(lines[1].as_bytes()[column - 1], b' ');
+        }
+        assert!(lines[1].ends_with("2999998590"));
+    }
+
+    #[test]
+    fn summary_adds_the_fee_rate_when_the_size_is_known() {
+        let report = TxReport {
+            vsize: Some(141),
+            weight: Some(561),
+            ..sample_report()
+        };
+        let mut out = Vec::new();
+        write_summary(&mut out, &report, Unit::Btc).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(
+            text.lines().nth(2),
+            Some("Fee rate: 10.000 sat/vB (141 vB, 561 WU)")
+        );
+        let json = report_json(&report, Unit::Sat).unwrap();
+        assert_eq!(json["vsize"], 141);
+        assert_eq!(json["fee_rate_sat_per_vb"], 10.0);
+    }
+
+    #[test]
+    fn fee_rate_line_has_one_decimal_and_reads_back() {
+        let report = TxReport {
+            vsize: Some(141),
+            ..sample_report()
+        };
+        let mut out = Vec::new();
+        write_report(&mut out, &report, Unit::Btc).unwrap();
+        write_fee_rate(&mut out, &report).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.lines().count(), OUT_TXT_FIELDS.len() + 1);
+        assert_eq!(text.lines().last(), Some("10.0"));
+        let read = read_report(text.as_bytes(), Unit::Btc).unwrap();
+        assert_eq!(read, sample_report());
+    }
+
+    #[test]
+    fn address_type_line_follows_the_fee_rate_and_reads_back() {
+        let report = TxReport {
+            address_type: Some(AddressType::Bech32m),
+            ..sample_report()
+        };
+        let mut out = Vec::new();
+        write_report(&mut out, &report, Unit::Btc).unwrap();
+        write_fee_rate(&mut out, &report).unwrap();
+        write_address_type(&mut out, &report).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.lines().count(), OUT_TXT_FIELDS.len() + 2);
+        // The size isn't known, so the fee rate line is empty.
+        assert_eq!(text.lines().nth(OUT_TXT_FIELDS.len()), Some(""));
+        assert_eq!(text.lines().last(), Some("bech32m"));
+        assert_eq!(read_report(text.as_bytes(), Unit::Btc).unwrap(), report);
+        let text = text.replace("bech32m", "segwit");
+        let err = read_report(text.as_bytes(), Unit::Btc).unwrap_err();
+        assert!(matches!(err, AppError::MalformedReport { line: 12, .. }));
+    }
+
+    #[test]
+    fn read_rejects_missing_lines() {
+        let err = read_report("only one line\n".as_bytes(), Unit::Btc).unwrap_err();
+        assert!(matches!(err, AppError::MalformedReport { line: 2, .. }));
+    }
+
+    #[test]
+    fn read_rejects_bad_amount() {
+        let mut out = Vec::new();
+        write_report(&mut out, &sample_report(), Unit::Btc).unwrap();
+        let text = String::from_utf8(out)
+            .unwrap()
+            .replace("20.00000000", "twenty");
+        let err = read_report(text.as_bytes(), Unit::Btc).unwrap_err();
+        assert!(matches!(
+            err,
+            AppError::MalformedReport {
+                line: 5,
+                field: "trader_output_amount",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn sat_unit_writes_integers() {
+        let report = sample_report();
+        let mut out = Vec::new();
+        write_report(&mut out, &report, Unit::Sat).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[2], "5000000000");
+        assert_eq!(lines[4], "2000000000");
+        assert_eq!(lines[6], "2999998590");
+        assert_eq!(lines[7], "1410");
+    }
+
+    #[test]
+    fn format_amount_in_each_unit() {
+        let amount = Amount::from_sat(1410);
+        assert_eq!(format_amount(amount, Unit::Btc), "0.00001410");
+        assert_eq!(format_amount(amount, Unit::Sat), "1410");
+    }
+
+    #[test]
+    fn tx_hex_strips_the_witness_on_the_second_line() {
+        use bitcoincore_rpc::bitcoin::absolute::LockTime;
+        use bitcoincore_rpc::bitcoin::transaction::Version;
+        use bitcoincore_rpc::bitcoin::{OutPoint, ScriptBuf, Sequence, TxIn, TxOut};
+
+        let mut tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let lines = |tx: &Transaction| {
+            let mut out = Vec::new();
+            write_tx_hex(&mut out, tx).unwrap();
+            String::from_utf8(out)
+                .unwrap()
+                .lines()
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        };
+        let legacy = lines(&tx);
+        assert_eq!(legacy[0], legacy[1]);
+
+        tx.input[0].witness.push([0xab; 64]);
+        let segwit = lines(&tx);
+        assert_eq!(segwit[1], legacy[1]);
+        assert!(segwit[0].len() > segwit[1].len());
+        assert!(segwit[0].contains(&"ab".repeat(64)));
+    }
+
+    #[test]
+    fn json_amounts_follow_the_unit() {
+        let report = sample_report();
+        let btc = report_json(&report, Unit::Btc).unwrap();
+        assert_eq!(btc["fee"], json!(0.0000141));
+        let sat = report_json(&report, Unit::Sat).unwrap();
+        assert_eq!(sat["fee"], json!(1410));
+        assert_eq!(sat["recipients"][0]["amount"], json!(2_000_000_000u64));
+    }
+}
@@ -0,0 +1,1072 @@
+// Extracting the details of the Miner -> Trader transaction and writing out.txt.
+
+use crate::backend::RpcBackend;
+use crate::cli::{OutputArgs, SendArgs};
+use crate::error::{AppError, RpcContext};
+use crate::out::{
+    address_type_name, report_json, write_address_type, write_columns, write_fee_rate,
+    write_report, write_summary, write_tx_hex, OUT_TXT_FIELDS,
+};
+use crate::rpc::{parallel_get_raw_transactions, rpc_error, RPC_INVALID_PARAMETER};
+use crate::tx::{dust_threshold, fee_for_tx};
+use crate::wallet::{check_addr, owns, prev_transactions, BalanceDelta, Wallets};
+use bitcoin::hex::DisplayHex;
+use bitcoincore_rpc::bitcoin::address::NetworkUnchecked;
+use bitcoincore_rpc::bitcoin::{
+    Address, Amount, BlockHash, Denomination, Network, OutPoint, ScriptBuf, Transaction, Txid,
+};
+use bitcoincore_rpc::json::{
+    AddressType, GetAddressInfoResult, GetRawTransactionResultVout,
+    GetRawTransactionResultVoutScriptPubKey,
+};
+use bitcoincore_rpc::Client;
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+// Writes `contents` to a temporary file next to `path` and renames it into place, so readers
+// only ever see the old file or the complete new one.
+fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let file_name = path.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "output path has no file name")
+    })?;
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let mut tmp_file = File::create(&tmp_path)?;
+    tmp_file.write_all(contents)?;
+    tmp_file.sync_all()?;
+    drop(tmp_file);
+    fs::rename(&tmp_path, path)
+}
+
+// One output paying the trader (or one of several recipients).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Payment {
+    pub address: String,
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    pub amount: Amount,
+}
+
+// Details of the Miner -> Trader transaction, as written to out.txt and out.json. Addresses
+// are left empty when the corresponding output couldn't be identified. out.txt has a fixed
+// 10-line format (see OUT_TXT_FIELDS), so the fields past those only go to out.json and the
+// summary, or to the opt-in out.txt lines.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TxReport {
+    pub txid: Txid,
+    // Comma-separated when the transaction spends several inputs.
+    pub miner_input_address: String,
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    pub miner_input_amount: Amount,
+    // Comma-separated, with the amounts summed, when the transaction pays several recipients.
+    pub trader_output_address: String,
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    pub trader_output_amount: Amount,
+    // Every recipient output on its own.
+    pub recipients: Vec<Payment>,
+    pub miner_change_address: String,
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    pub miner_change_amount: Amount,
+    // Type of the Miner's change address, if there is change. Only in out.txt with
+    // `--emit-address-type`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address_type: Option<AddressType>,
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    pub fee: Amount,
+    // Virtual size in vbytes and weight in weight units.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vsize: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u64>,
+    pub block_height: u64,
+    pub block_hash: BlockHash,
+    // Net balance changes of both wallets, known only when the send happened in the same run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance_delta: Option<BalanceDelta>,
+}
+
+impl TxReport {
+    // The fee per vbyte in sats, rounded to the thousandth the node quotes fee rates in, if the
+    // size is known.
+    pub fn fee_rate_sat_per_vb(&self) -> Option<f64> {
+        let vsize = self.vsize.filter(|&vsize| vsize > 0)?;
+        Some((self.fee.to_sat() as f64 / vsize as f64 * 1000.0).round() / 1000.0)
+    }
+}
+
+// Who an output of the Miner -> Trader transaction pays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputKind {
+    // Pays the trader's receiving address.
+    Trader,
+    // Pays an address of the sending (Miner) wallet, i.e. change.
+    MinerChange,
+    // Pays an address that belongs to neither.
+    External,
+    // Has no address (bare script, OP_RETURN) or its owner couldn't be determined.
+    Unknown,
+}
+
+// How the Trader's output of a transaction is recognized.
+pub enum TraderOutput<'a, B = Client> {
+    // The output pays one of these addresses, known from the run that made the transaction.
+    Addresses(&'a [Address]),
+    // The address isn't known; the output pays an address this (Trader) wallet owns.
+    OwnedBy(&'a B),
+}
+
+// Not derived: the derives would require `B: Copy`, and only the reference is copied.
+impl<B> Clone for TraderOutput<'_, B> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<B> Copy for TraderOutput<'_, B> {}
+
+impl<B: RpcBackend> TraderOutput<'_, B> {
+    fn matches(self, addr: &Address) -> bool {
+        match self {
+            TraderOutput::Addresses(trader_addrs) => trader_addrs.contains(addr),
+            TraderOutput::OwnedBy(trader_wallet) => owns(trader_wallet, addr),
+        }
+    }
+}
+
+// Works out who `addr`, the address of an output, belongs to by checking it against `trader`
+// and asking `wallet` whether the address is its own.
+fn classify_output<B: RpcBackend>(
+    wallet: &B,
+    addr: Option<&Address>,
+    trader: TraderOutput<B>,
+) -> OutputKind {
+    let Some(addr) = addr else {
+        return OutputKind::Unknown;
+    };
+    if trader.matches(addr) {
+        return OutputKind::Trader;
+    }
+    match wallet.get_address_info(addr) {
+        Ok(info) if info.is_mine.unwrap_or(false) => OutputKind::MinerChange,
+        Ok(_) => OutputKind::External,
+        Err(e) => {
+            debug!("Could not look up address {addr}: {e}");
+            OutputKind::Unknown
+        }
+    }
+}
+
+impl OutputKind {
+    // Whose the output is, as `decode` shows it.
+    fn owner(self) -> &'static str {
+        match self {
+            OutputKind::Trader => "trader",
+            OutputKind::MinerChange => "miner",
+            OutputKind::External => "external",
+            OutputKind::Unknown => "-",
+        }
+    }
+}
+
+// Returns the `getnewaddress` type of a wallet address. Plain P2SH and P2SH-wrapped segwit
+// look the same on chain, so the redeem script from `getaddressinfo` tells them apart.
+fn address_type_of(addr: &Address, info: &GetAddressInfoResult) -> Option<AddressType> {
+    use bitcoincore_rpc::bitcoin::AddressType as Kind;
+
+    match addr.address_type()? {
+        Kind::P2pkh => Some(AddressType::Legacy),
+        Kind::P2sh => {
+            let redeem_script = ScriptBuf::from(info.hex.clone()?);
+            if redeem_script.is_witness_program() {
+                Some(AddressType::P2shSegwit)
+            } else {
+                Some(AddressType::Legacy)
+            }
+        }
+        Kind::P2wpkh | Kind::P2wsh => Some(AddressType::Bech32),
+        Kind::P2tr => Some(AddressType::Bech32m),
+        _ => None,
+    }
+}
+
+// Returns the address `vout` pays to, if its script has one.
+fn output_address(
+    vout: &GetRawTransactionResultVout,
+    network: Network,
+) -> Result<Option<Address>, AppError> {
+    script_address(&vout.script_pub_key, network)
+}
+
+// Returns the address `script_pub_key` pays to, if it has one.
+fn script_address(
+    script_pub_key: &GetRawTransactionResultVoutScriptPubKey,
+    network: Network,
+) -> Result<Option<Address>, AppError> {
+    // Core 22+ reports a single `address`; older nodes fill the `addresses` list instead.
+    script_pub_key
+        .address
+        .as_ref()
+        .or(script_pub_key.addresses.first())
+        .map(|a| check_addr(a.clone(), network))
+        .transpose()
+}
+
+// Returns the address (if its script has one) and value of each output `outpoints` spends, in
+// order. `gettxout` answers that without shipping the whole transaction, but only while the
+// output is unspent. The transactions holding the spent ones are fetched afterwards, each only
+// once and all together: from `wallet` where it has them and the rest with `from_node`.
+fn prevouts(
+    wallet: &impl RpcBackend,
+    outpoints: &[OutPoint],
+    network: Network,
+    from_node: impl FnOnce(&[Txid]) -> Result<Vec<Transaction>, AppError>,
+) -> Result<Vec<(Option<Address>, Amount)>, AppError> {
+    let mut unspent = Vec::with_capacity(outpoints.len());
+    let mut txids: Vec<Txid> = Vec::new();
+    for outpoint in outpoints {
+        let out = wallet
+            .get_tx_out(&outpoint.txid, outpoint.vout, Some(true))
+            .context(|| format!("looking up the output {outpoint}"))?;
+        let prevout = match out {
+            Some(out) => Some((script_address(&out.script_pub_key, network)?, out.value)),
+            None => {
+                if !txids.contains(&outpoint.txid) {
+                    txids.push(outpoint.txid);
+                }
+                None
+            }
+        };
+        unspent.push(prevout);
+    }
+    let prev_txs: HashMap<Txid, Transaction> = txids
+        .iter()
+        .copied()
+        .zip(prev_transactions(wallet, &txids, from_node)?)
+        .collect();
+    outpoints
+        .iter()
+        .zip(unspent)
+        .map(|(outpoint, unspent)| {
+            if let Some(prevout) = unspent {
+                return Ok(prevout);
+            }
+            let output = prev_txs[&outpoint.txid]
+                .output
+                .get(outpoint.vout as usize)
+                .ok_or(AppError::OutputNotFound {
+                    txid: outpoint.txid,
+                    vout: outpoint.vout,
+                })?;
+            let address = Address::from_script(&output.script_pubkey, network).ok();
+            Ok((address, output.value))
+        })
+        .collect()
+}
+
+// The transactions `txids` from the node, in order: in one batch if possible, otherwise in
+// parallel.
+fn node_transactions<B: RpcBackend>(
+    wallets: &Wallets<B>,
+    txids: &[Txid],
+) -> Result<Vec<Transaction>, AppError> {
+    match (wallets.miner.get_raw_transactions(txids), &wallets.node) {
+        (Ok(txs), _) => Ok(txs),
+        (Err(e), Some(node)) => {
+            debug!("Batched lookup failed ({e}); fetching the transactions in parallel");
+            parallel_get_raw_transactions(node, txids, wallets.rpc_concurrency)
+                .context(|| "fetching the transactions the inputs spend")
+        }
+        (Err(e), None) => Err(e).context(|| "fetching the transactions the inputs spend"),
+    }
+}
+
+// Collects the details of the confirmed transaction `txid` sent from the Miner wallet to the
+// output `trader` picks out. Every address in the transaction must belong to `network`.
+pub fn build_report<B: RpcBackend>(
+    wallets: &Wallets<B>,
+    txid: &Txid,
+    trader: TraderOutput<B>,
+    network: Network,
+) -> Result<TxReport, AppError> {
+    let wallet = &wallets.miner;
+    // 1. Get the confirmed transaction details
+    let tx_info = wallet
+        .get_transaction(txid, None)
+        .context(|| format!("looking up {txid} in the Miner wallet"))?;
+    let block_hash = tx_info
+        .info
+        .blockhash
+        .ok_or(AppError::TransactionUnconfirmed(*txid))?;
+    let block = wallet
+        .get_block_info(&block_hash)
+        .context(|| format!("fetching block {block_hash}"))?;
+
+    // 2. Get the raw transaction and decode it
+    let raw_tx = wallet
+        .get_raw_transaction(txid, Some(&block_hash))
+        .context(|| format!("fetching the raw transaction {txid}"))?;
+    let decoded_tx = wallet
+        .decode_raw_transaction(&raw_tx, None)
+        .context(|| format!("decoding the raw transaction {txid}"))?;
+
+    // 3. Find input addresses and amounts (from the previous outputs being spent)
+    let outpoints = decoded_tx
+        .vin
+        .iter()
+        .map(|input| match (input.txid, input.vout) {
+            (Some(txid), Some(vout)) => Ok(OutPoint { txid, vout }),
+            _ => Err(AppError::UnexpectedCoinbase(*txid)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let from_node = |missing: &[Txid]| node_transactions(wallets, missing);
+    let inputs: Vec<(String, Amount)> = prevouts(wallet, &outpoints, network, from_node)?
+        .into_iter()
+        .map(|(address, amount)| (address.map(|a| a.to_string()).unwrap_or_default(), amount))
+        .collect();
+    // A single funding input is the common case; with several, list them all on one line.
+    let miner_input_address = inputs
+        .iter()
+        .map(|(address, _)| address.as_str())
+        .collect::<Vec<_>>()
+        .join(",");
+    let miner_input_amount = inputs
+        .iter()
+        .try_fold(Amount::ZERO, |total, (_, amount)| {
+            total.checked_add(*amount)
+        })
+        .ok_or(bitcoincore_rpc::Error::UnexpectedStructure)?;
+    if inputs.len() > 1 {
+        debug!("Transaction spends {} inputs:", inputs.len());
+        for (address, amount) in &inputs {
+            debug!("  Address: {address}, Value: {:.8}", amount.to_btc());
+        }
+    }
+
+    // 4. Find outputs: trader's output(s), miner's change
+    let mut recipients = Vec::new();
+    let mut miner_change_address = String::new();
+    let mut miner_change_amount = Amount::ZERO;
+    let mut address_type = None;
+    // Everything else still counts towards the outputs total: payments to third parties and
+    // scriptless/data-carrier (OP_RETURN) outputs, whose value is usually zero.
+    let mut external_output_total = Amount::ZERO;
+    let mut data_output_total = Amount::ZERO;
+    debug!("Decoded transaction outputs:");
+    for vout in &decoded_tx.vout {
+        let addr = output_address(vout, network)?;
+        let kind = classify_output(wallet, addr.as_ref(), trader);
+        // The script is the only way to tell outputs without an address apart.
+        debug!(
+            "  #{} {kind:?}: Address: {}, Value: {:.8}, scriptPubKey: {}",
+            vout.n,
+            addr.as_ref().map(|a| a.to_string()).unwrap_or_default(),
+            vout.value.to_btc(),
+            vout.script_pub_key.hex.to_lower_hex_string()
+        );
+        match kind {
+            OutputKind::Trader => recipients.push(Payment {
+                // Classified as the trader's, so the output does have an address.
+                address: addr.as_ref().map(|a| a.to_string()).unwrap_or_default(),
+                amount: vout.value,
+            }),
+            OutputKind::MinerChange => {
+                // Classified as change, so the output does have an address.
+                if let Some(addr) = &addr {
+                    let info = wallet.get_address_info(addr)?;
+                    address_type = address_type_of(addr, &info);
+                    miner_change_address = addr.to_string();
+                }
+                miner_change_amount = vout.value;
+            }
+            OutputKind::Unknown if addr.is_none() => {
+                data_output_total += vout.value;
+            }
+            OutputKind::External | OutputKind::Unknown => external_output_total += vout.value,
+        }
+    }
+
+    if recipients.is_empty() {
+        warn!("No output of {txid} pays the trader.");
+    }
+    let trader_output_address = recipients
+        .iter()
+        .map(|payment| payment.address.as_str())
+        .collect::<Vec<_>>()
+        .join(",");
+    let trader_output_amount = recipients
+        .iter()
+        .try_fold(Amount::ZERO, |total, payment| {
+            total.checked_add(payment.amount)
+        })
+        .ok_or(bitcoincore_rpc::Error::UnexpectedStructure)?;
+
+    // 5. Get the transaction fee as tracked by the wallet
+    let fee = fee_for_tx(wallet, txid)?;
+    let accounted = [
+        trader_output_amount,
+        miner_change_amount,
+        external_output_total,
+        data_output_total,
+        fee,
+    ]
+    .into_iter()
+    .try_fold(Amount::ZERO, Amount::checked_add);
+    if accounted != Some(miner_input_amount) {
+        warn!(
+            "Inputs of {txid} ({miner_input_amount}) don't match outputs plus fee: trader \
+             {trader_output_amount}, change {miner_change_amount}, external \
+             {external_output_total}, data {data_output_total}, fee {fee}"
+        );
+    }
+
+    Ok(TxReport {
+        txid: *txid,
+        miner_input_address,
+        miner_input_amount,
+        trader_output_address,
+        trader_output_amount,
+        recipients,
+        miner_change_address,
+        miner_change_amount,
+        address_type,
+        fee,
+        vsize: Some(u64::from(decoded_tx.vsize)),
+        weight: Some(u64::from(decoded_tx.weight)),
+        block_height: block.height as u64,
+        block_hash,
+        balance_delta: None,
+    })
+}
+
+// Writes what `decode` shows of `tx`: its ids and size, the outputs its inputs spend, and the
+// address, amount and owner of each output, with the owner worked out the same way as for a
+// report (the Miner wallet is `wallet`, the Trader `trader`).
+pub(crate) fn write_decoded_tx<B: RpcBackend>(
+    w: &mut impl Write,
+    wallet: &B,
+    trader: TraderOutput<B>,
+    tx: &Transaction,
+    network: Network,
+) -> Result<(), AppError> {
+    let decoded = wallet
+        .decode_raw_transaction(tx, None)
+        .context(|| "decoding the transaction")?;
+    writeln!(w, "Transaction: {}", decoded.txid)?;
+    writeln!(w, "Witness txid: {}", decoded.hash)?;
+    writeln!(
+        w,
+        "Size: {} B, {} vB, {} WU",
+        tx.total_size(),
+        tx.vsize(),
+        tx.weight().to_wu()
+    )?;
+    writeln!(
+        w,
+        "Version: {}, locktime: {}",
+        decoded.version, decoded.locktime
+    )?;
+
+    writeln!(w)?;
+    let mut inputs = vec![vec![
+        "#".to_owned(),
+        "SPENDS".to_owned(),
+        "SEQUENCE".to_owned(),
+    ]];
+    for (n, vin) in decoded.vin.iter().enumerate() {
+        let spends = match (vin.txid, vin.vout) {
+            (Some(txid), Some(vout)) => format!("{txid}:{vout}"),
+            _ => "coinbase".to_owned(),
+        };
+        inputs.push(vec![
+            n.to_string(),
+            spends,
+            format!("{:#010x}", vin.sequence),
+        ]);
+    }
+    write_columns(w, &inputs)?;
+
+    writeln!(w)?;
+    let mut outputs = vec![vec![
+        "#".to_owned(),
+        "AMOUNT (BTC)".to_owned(),
+        "ADDRESS".to_owned(),
+        "OWNER".to_owned(),
+    ]];
+    for vout in &decoded.vout {
+        let addr = output_address(vout, network)?;
+        let kind = classify_output(wallet, addr.as_ref(), trader);
+        outputs.push(vec![
+            vout.n.to_string(),
+            format!("{:.8}", vout.value.to_btc()),
+            addr.map(|a| a.to_string())
+                .unwrap_or_else(|| "-".to_owned()),
+            kind.owner().to_owned(),
+        ]);
+    }
+    write_columns(w, &outputs)?;
+    let total: Amount = decoded.vout.iter().map(|vout| vout.value).sum();
+    writeln!(w, "Total output: {:.8} BTC", total.to_btc())?;
+    Ok(())
+}
+
+// Checks that `hash` is the block at `height` of the active chain. Returns false if the chain
+// doesn't reach `height` (yet), and fails with ReorgDetected if another block is there.
+pub fn verify_height_hash(
+    rpc: &impl RpcBackend,
+    height: u64,
+    hash: &BlockHash,
+) -> Result<bool, AppError> {
+    let actual = match rpc.get_block_hash(height) {
+        Ok(actual) => actual,
+        Err(e) if rpc_error(&e).is_some_and(|rpc_err| rpc_err.code == RPC_INVALID_PARAMETER) => {
+            return Ok(false);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if actual != *hash {
+        return Err(AppError::ReorgDetected {
+            height,
+            expected: *hash,
+            actual,
+        });
+    }
+    Ok(true)
+}
+
+// The largest dust threshold (see tx::dust_threshold) of the trader outputs of `report`.
+// Mismatches between the requested and detected trader amounts up to it are ignored.
+fn trader_dust_threshold(report: &TxReport) -> Amount {
+    report
+        .recipients
+        .iter()
+        .filter_map(|payment| payment.address.parse::<Address<NetworkUnchecked>>().ok())
+        .map(|address| dust_threshold(&address.assume_checked().script_pubkey()))
+        .max()
+        .unwrap_or(Amount::ZERO)
+}
+
+// Extracts the details of `txid` and writes them where `output` says. When the send that made
+// `txid` happened in this run, `sent` holds what it asked for, which is checked against what
+// the transaction actually does, and how it changed the wallet balances. With `verify`, the
+// block height and hash are checked against the active chain before anything is written.
+pub(crate) fn run_report<B: RpcBackend>(
+    wallets: &Wallets<B>,
+    network: Network,
+    txid: &Txid,
+    trader: TraderOutput<B>,
+    sent: Option<(&SendArgs, BalanceDelta)>,
+    output: &OutputArgs,
+    verify: bool,
+) -> Result<(), AppError> {
+    // Extract all required transaction details
+    let miner_wallet = &wallets.miner;
+    let mut report = build_report(wallets, txid, trader, network)?;
+    if verify && !verify_height_hash(miner_wallet, report.block_height, &report.block_hash)? {
+        // The confirming block is past the tip, so it isn't in the active chain any more.
+        return Err(AppError::TransactionUnconfirmed(*txid));
+    }
+    let requested = sent.map(|(requested, _)| requested);
+    report.balance_delta = sent.map(|(_, balance_delta)| balance_delta);
+    if let Some(delta) = &report.balance_delta {
+        info!(
+            "Net balance change: Miner {}, Trader {}",
+            delta.miner, delta.trader
+        );
+    }
+    info!("trader_output_address: {}", report.trader_output_address);
+    info!(
+        "trader_output_amount: {:.8}",
+        report.trader_output_amount.to_btc()
+    );
+    info!("miner_change_address: {}", report.miner_change_address);
+    if let Some(requested) = requested {
+        let detected = report.trader_output_amount;
+        let expected = requested.total_amount();
+        let difference = detected.max(expected) - detected.min(expected);
+        if difference > trader_dust_threshold(&report) {
+            warn!(
+                "The trader output of {txid} is {:.8} BTC but {:.8} BTC was sent; out.txt may \
+                 not describe the intended payment.",
+                detected.to_btc(),
+                expected.to_btc()
+            );
+        }
+    }
+    match (report.address_type, requested.map(|r| r.address_type)) {
+        (Some(change_type), Some(requested)) if change_type != requested => warn!(
+            "Change output uses {change_type:?} addresses although {requested:?} was requested."
+        ),
+        (Some(change_type), _) => info!("address_type: {}", address_type_name(change_type)),
+        (None, _) => {}
+    }
+    info!(
+        "miner_change_amount: {:.8}",
+        report.miner_change_amount.to_btc()
+    );
+
+    // 6. Write to the output file (../out.txt by default) in the required format, plus the
+    // same details as JSON next to it (out.json) unless `--format` says otherwise
+    let out_path = env::current_dir()?.join(&output.out);
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if output.format.text() {
+        let mut out = Vec::new();
+        write_report(&mut out, &report, output.amount_unit)?;
+        if output.emit_feerate {
+            write_fee_rate(&mut out, &report)?;
+        }
+        if output.emit_address_type {
+            write_address_type(&mut out, &report)?;
+        }
+        write_atomically(&out_path, &out)?;
+        let out_path = fs::canonicalize(&out_path)?;
+        info!("Transaction details written to {}", out_path.display());
+    }
+    if output.format.json() {
+        let json_path = out_path.with_extension("json");
+        let json = report_json(&report, output.amount_unit).map_err(io::Error::from)?;
+        let mut out = serde_json::to_vec_pretty(&json).map_err(io::Error::from)?;
+        out.push(b'\n');
+        write_atomically(&json_path, &out)?;
+        let json_path = fs::canonicalize(&json_path)?;
+        info!("Transaction details written to {}", json_path.display());
+    }
+    if output.emit_hex {
+        let hex_path = out_path.with_file_name("tx.hex");
+        let tx = miner_wallet.get_raw_transaction(txid, Some(&report.block_hash))?;
+        let mut out = Vec::new();
+        write_tx_hex(&mut out, &tx)?;
+        write_atomically(&hex_path, &out)?;
+        let hex_path = fs::canonicalize(&hex_path)?;
+        info!("Raw transaction hex written to {}", hex_path.display());
+    }
+    if !output.no_summary {
+        write_summary(&mut io::stdout(), &report, output.amount_unit)?;
+    }
+
+    Ok(())
+}
+
+// Reads out.txt back and re-derives every line from the wallets and the chain, without going
+// through build_report.
+pub(crate) fn verify_out_txt(
+    rpc: &impl RpcBackend,
+    wallets: &Wallets<impl RpcBackend>,
+    network: Network,
+    path: &Path,
+) -> Result<(), AppError> {
+    let contents = fs::read_to_string(path)?;
+    let lines: Vec<&str> = contents.lines().collect();
+    if lines.len() != OUT_TXT_FIELDS.len() {
+        return Err(AppError::SelftestFailed {
+            line: lines.len().min(OUT_TXT_FIELDS.len()) + 1,
+            field: "file",
+            reason: format!(
+                "expected {} lines, found {}",
+                OUT_TXT_FIELDS.len(),
+                lines.len()
+            ),
+        });
+    }
+    let fail = |index: usize, reason: String| AppError::SelftestFailed {
+        line: index + 1,
+        field: OUT_TXT_FIELDS[index],
+        reason,
+    };
+    let amount = |index: usize| {
+        Amount::from_str_in(lines[index], Denomination::Bitcoin).map_err(|e| {
+            fail(
+                index,
+                format!("'{}' is not a BTC amount: {e}", lines[index]),
+            )
+        })
+    };
+    let address = |index: usize, text: &str| {
+        text.parse::<Address<NetworkUnchecked>>()
+            .map_err(|e| fail(index, format!("'{text}' is not an address: {e}")))?
+            .require_network(network)
+            .map_err(|e| fail(index, format!("'{text}' is not a {network} address: {e}")))
+    };
+
+    // 1. The transaction is in the Miner wallet and confirmed.
+    let txid: Txid = lines[0]
+        .parse()
+        .map_err(|e| fail(0, format!("'{}' is not a txid: {e}", lines[0])))?;
+    let tx_info = wallets
+        .miner
+        .get_transaction(&txid, None)
+        .map_err(|e| fail(0, format!("not known to the Miner wallet: {e}")))?;
+    let confirmed_in = match tx_info.info.blockhash {
+        Some(hash) if tx_info.info.confirmations > 0 => hash,
+        _ => return Err(fail(0, format!("{txid} is not confirmed"))),
+    };
+    let tx = tx_info
+        .transaction()
+        .map_err(bitcoincore_rpc::Error::from)?;
+
+    // 2-3. Every input spends a Miner output, and together they add up to the input amount.
+    let mut input_addresses = Vec::new();
+    let mut input_total = Amount::ZERO;
+    for input in &tx.input {
+        let prev = &input.previous_output;
+        let prev_tx = wallets
+            .miner
+            .get_transaction(&prev.txid, None)?
+            .transaction()
+            .map_err(bitcoincore_rpc::Error::from)?;
+        let prevout = prev_tx
+            .output
+            .get(prev.vout as usize)
+            .ok_or(AppError::OutputNotFound {
+                txid: prev.txid,
+                vout: prev.vout,
+            })?;
+        let prev_address = Address::from_script(&prevout.script_pubkey, network)
+            .map_err(|e| fail(1, format!("input {prev} has no address: {e}")))?;
+        if !owns(&wallets.miner, &prev_address) {
+            return Err(fail(1, format!("{prev_address} is not a Miner address")));
+        }
+        input_addresses.push(prev_address.to_string());
+        input_total += prevout.value;
+    }
+    if lines[1] != input_addresses.join(",") {
+        return Err(fail(
+            1,
+            format!("inputs spend from {}", input_addresses.join(",")),
+        ));
+    }
+    if amount(2)? != input_total {
+        return Err(fail(2, format!("inputs add up to {input_total}")));
+    }
+
+    // 4-5. The trader addresses belong to the Trader wallet and are paid the trader amount.
+    let paid_to = |addr: &Address| {
+        tx.output
+            .iter()
+            .filter(|out| out.script_pubkey == addr.script_pubkey())
+            .map(|out| out.value)
+            .sum::<Amount>()
+    };
+    let mut trader_total = Amount::ZERO;
+    for text in lines[3].split(',') {
+        let addr = address(3, text)?;
+        if !owns(&wallets.trader, &addr) {
+            return Err(fail(3, format!("{addr} is not a Trader address")));
+        }
+        let paid = paid_to(&addr);
+        if paid == Amount::ZERO {
+            return Err(fail(3, format!("{txid} pays nothing to {addr}")));
+        }
+        trader_total += paid;
+    }
+    if amount(4)? != trader_total {
+        return Err(fail(4, format!("trader outputs add up to {trader_total}")));
+    }
+
+    // 6-7. The change address belongs to the Miner and receives the change amount.
+    let change = if lines[5].is_empty() {
+        Amount::ZERO
+    } else {
+        let addr = address(5, lines[5])?;
+        if !owns(&wallets.miner, &addr) {
+            return Err(fail(5, format!("{addr} is not a Miner address")));
+        }
+        paid_to(&addr)
+    };
+    if amount(6)? != change {
+        return Err(fail(6, format!("change output is {change}")));
+    }
+
+    // 8. The fee is what the inputs leave over after the outputs.
+    let output_total = tx.output.iter().map(|out| out.value).sum::<Amount>();
+    let fee = input_total
+        .checked_sub(output_total)
+        .ok_or(AppError::NegativeFee {
+            txid,
+            excess: output_total - input_total,
+        })?;
+    if amount(7)? != fee {
+        return Err(fail(7, format!("inputs minus outputs is {fee}")));
+    }
+
+    // 9-10. The block at that height is the block that confirmed the transaction.
+    let height: u64 = lines[8]
+        .parse()
+        .map_err(|e| fail(8, format!("'{}' is not a height: {e}", lines[8])))?;
+    let hash_at_height = rpc.get_block_hash(height)?;
+    if lines[9] != hash_at_height.to_string() {
+        return Err(fail(
+            8,
+            format!("block {height} is {hash_at_height}, not {}", lines[9]),
+        ));
+    }
+    if hash_at_height != confirmed_in {
+        return Err(fail(9, format!("{txid} was confirmed in {confirmed_in}")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use bitcoincore_rpc::bitcoin::absolute::LockTime;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use bitcoincore_rpc::bitcoin::transaction::Version;
+    use bitcoincore_rpc::bitcoin::{Sequence, TxIn, TxOut, WPubkeyHash, Witness};
+
+    fn address(n: u8) -> Address {
+        let script = ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([n; 20]));
+        Address::from_script(&script, Network::Regtest).unwrap()
+    }
+
+    fn tx(inputs: &[OutPoint], outputs: &[(&Address, u64)]) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: inputs
+                .iter()
+                .map(|outpoint| TxIn {
+                    previous_output: *outpoint,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: outputs
+                .iter()
+                .map(|(address, sats)| TxOut {
+                    value: Amount::from_sat(*sats),
+                    script_pubkey: address.script_pubkey(),
+                })
+                .collect(),
+        }
+    }
+
+    fn block(n: u8) -> BlockHash {
+        BlockHash::from_byte_array([n; 32])
+    }
+
+    // The Miner spends a 50 BTC output to pay the Trader 20 BTC and pays a 1000 sat fee,
+    // sending the rest to `rest`, which is the Miner's own change address if `rest_is_mine`.
+    struct Scenario {
+        miner: MockBackend,
+        txid: Txid,
+        funding: Address,
+        trader: Address,
+        rest: Address,
+    }
+
+    fn scenario(rest_is_mine: bool) -> Scenario {
+        let (funding, trader, rest) = (address(1), address(2), address(3));
+        let coinbase = tx(&[OutPoint::null()], &[(&funding, 5_000_000_000)]);
+        let spend = tx(
+            &[OutPoint::new(coinbase.txid(), 0)],
+            &[(&trader, 2_000_000_000), (&rest, 2_999_999_000)],
+        );
+        let txid = spend.txid();
+        let mut miner = MockBackend::new(Network::Regtest)
+            .with_balance(Amount::from_sat(2_999_999_000))
+            .with_transaction(coinbase, Some((block(1), 1)), None)
+            .with_transaction(spend, Some((block(102), 102)), Some(Amount::from_sat(1000)))
+            .owning(&funding);
+        if rest_is_mine {
+            miner = miner.owning(&rest);
+        }
+        Scenario {
+            miner,
+            txid,
+            funding,
+            trader,
+            rest,
+        }
+    }
+
+    // Wallets on the mock backend. Without a node, every lookup goes through `miner`.
+    fn wallets(miner: MockBackend, trader: MockBackend) -> Wallets<MockBackend> {
+        Wallets {
+            miner,
+            trader,
+            node: None,
+            rpc_concurrency: 1,
+        }
+    }
+
+    fn report(s: Scenario) -> Result<TxReport, AppError> {
+        let trader_addrs = [s.trader.clone()];
+        build_report(
+            &wallets(s.miner, MockBackend::new(Network::Regtest)),
+            &s.txid,
+            TraderOutput::Addresses(&trader_addrs),
+            Network::Regtest,
+        )
+    }
+
+    #[test]
+    fn report_describes_a_payment_with_change() {
+        let s = scenario(true);
+        // Without witnesses every byte weighs 4 weight units.
+        let spend_vsize = s.miner.get_raw_transaction(&s.txid, None).unwrap().vsize() as u64;
+        let (txid, funding, trader, rest) =
+            (s.txid, s.funding.clone(), s.trader.clone(), s.rest.clone());
+        assert_eq!(
+            report(s).unwrap(),
+            TxReport {
+                txid,
+                miner_input_address: funding.to_string(),
+                miner_input_amount: Amount::from_sat(5_000_000_000),
+                trader_output_address: trader.to_string(),
+                trader_output_amount: Amount::from_sat(2_000_000_000),
+                recipients: vec![Payment {
+                    address: trader.to_string(),
+                    amount: Amount::from_sat(2_000_000_000),
+                }],
+                miner_change_address: rest.to_string(),
+                miner_change_amount: Amount::from_sat(2_999_999_000),
+                address_type: Some(AddressType::Bech32),
+                fee: Amount::from_sat(1000),
+                vsize: Some(spend_vsize),
+                weight: Some(spend_vsize * 4),
+                block_height: 102,
+                block_hash: block(102),
+                balance_delta: None,
+            }
+        );
+    }
+
+    #[test]
+    fn unspent_prevouts_come_from_gettxout() {
+        let (funding, trader) = (address(1), address(2));
+        let funding_tx = tx(&[OutPoint::null()], &[(&funding, 5_000_000_000)]);
+        let outpoint = OutPoint::new(funding_tx.txid(), 0);
+        let spend = tx(&[outpoint], &[(&trader, 4_999_999_000)]);
+        let txid = spend.txid();
+        // Neither the wallet nor the node (no txindex) could look `funding_tx` up.
+        let miner = MockBackend::new(Network::Regtest)
+            .with_transaction(spend, Some((block(102), 102)), Some(Amount::from_sat(1000)))
+            .with_unspent(outpoint, funding_tx.output[0].clone());
+        let trader_addrs = [trader];
+        let report = build_report(
+            &wallets(miner, MockBackend::new(Network::Regtest)),
+            &txid,
+            TraderOutput::Addresses(&trader_addrs),
+            Network::Regtest,
+        )
+        .unwrap();
+        assert_eq!(report.miner_input_address, funding.to_string());
+        assert_eq!(report.miner_input_amount, Amount::from_sat(5_000_000_000));
+    }
+
+    #[test]
+    fn spent_transactions_the_wallet_lacks_come_from_the_node() {
+        let (funding, trader) = (address(1), address(2));
+        let funding_tx = tx(&[OutPoint::null()], &[(&funding, 5_000_000_000)]);
+        let spend = tx(
+            &[OutPoint::new(funding_tx.txid(), 0)],
+            &[(&trader, 4_999_999_000)],
+        );
+        let txid = spend.txid();
+        let miner = MockBackend::new(Network::Regtest)
+            .with_transaction(spend, Some((block(102), 102)), Some(Amount::from_sat(1000)))
+            .with_indexed_transaction(funding_tx);
+        let wallets = wallets(miner, MockBackend::new(Network::Regtest).owning(&trader));
+        let report = build_report(
+            &wallets,
+            &txid,
+            TraderOutput::OwnedBy(&wallets.trader),
+            Network::Regtest,
+        )
+        .unwrap();
+        assert_eq!(report.miner_input_address, funding.to_string());
+        assert_eq!(report.miner_input_amount, Amount::from_sat(5_000_000_000));
+        assert_eq!(report.trader_output_address, trader.to_string());
+    }
+
+    #[test]
+    fn amount_mismatches_are_tolerated_up_to_the_trader_dust_threshold() {
+        let mut report = report(scenario(true)).unwrap();
+        // P2WPKH
+        assert_eq!(trader_dust_threshold(&report), Amount::from_sat(294));
+        let p2tr = ScriptBuf::from_bytes([&[0x51, 0x20][..], &[7; 32]].concat());
+        report.recipients.push(Payment {
+            address: Address::from_script(&p2tr, Network::Regtest)
+                .unwrap()
+                .to_string(),
+            amount: Amount::ONE_BTC,
+        });
+        assert_eq!(trader_dust_threshold(&report), Amount::from_sat(330));
+        report.recipients.clear();
+        assert_eq!(trader_dust_threshold(&report), Amount::ZERO);
+    }
+
+    #[test]
+    fn outputs_the_miner_does_not_own_are_not_change() {
+        let report = report(scenario(false)).unwrap();
+        assert_eq!(report.trader_output_amount, Amount::from_sat(2_000_000_000));
+        assert_eq!(report.miner_change_address, "");
+        assert_eq!(report.miner_change_amount, Amount::ZERO);
+        assert_eq!(report.address_type, None);
+        assert_eq!(report.fee, Amount::from_sat(1000));
+    }
+
+    #[test]
+    fn decoded_outputs_show_their_owners() {
+        let s = scenario(true);
+        let spend = s.miner.get_raw_transaction(&s.txid, None).unwrap();
+        let trader_addrs = [s.trader.clone()];
+        let mut out = Vec::new();
+        write_decoded_tx(
+            &mut out,
+            &s.miner,
+            TraderOutput::Addresses(&trader_addrs),
+            &spend,
+            Network::Regtest,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.starts_with(&format!("Transaction: {}\n", s.txid)),
+            "{out}"
+        );
+        assert!(out.contains(&format!(
+            "0  {}:0  0xfffffffd",
+            spend.input[0].previous_output.txid
+        )));
+        assert!(
+            out.contains(&format!("0  20.00000000   {}  trader", s.trader)),
+            "{out}"
+        );
+        assert!(
+            out.contains(&format!("1  29.99999000   {}  miner", s.rest)),
+            "{out}"
+        );
+        assert!(out.ends_with("Total output: 49.99999000 BTC\n"), "{out}");
+    }
+
+    #[test]
+    fn unconfirmed_transactions_are_refused() {
+        let mut s = scenario(true);
+        let spend = tx(
+            &[OutPoint::new(s.txid, 1)],
+            &[(&s.trader, 1_000_000_000), (&s.rest, 1_999_998_000)],
+        );
+        s.txid = spend.txid();
+        s.miner = s
+            .miner
+            .with_transaction(spend, None, Some(Amount::from_sat(1000)));
+        let txid = s.txid;
+        assert!(matches!(
+            report(s),
+            Err(AppError::TransactionUnconfirmed(unconfirmed)) if unconfirmed == txid
+        ));
+    }
+}
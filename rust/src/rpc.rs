@@ -0,0 +1,248 @@
+// Calls to RPCs the typed client doesn't cover, and Bitcoin Core error codes.
+
+use crate::backend::RpcBackend;
+use crate::config::Node;
+use bitcoincore_rpc::bitcoin::consensus::encode::deserialize;
+use bitcoincore_rpc::bitcoin::hex::FromHex;
+use bitcoincore_rpc::bitcoin::{Amount, BlockHash, Transaction, Txid};
+use bitcoincore_rpc::Client;
+use log::debug;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::value::to_raw_value;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::panic;
+use std::thread;
+
+// Calls `method` with `args` and deserializes the result into `T`. This is how to reach RPCs
+// the typed client doesn't expose (or arguments it leaves out): derive `Deserialize` for the
+// part of the result that is needed.
+pub(crate) fn rpc_call<T: DeserializeOwned>(
+    rpc: &impl RpcBackend,
+    method: &str,
+    args: &[Value],
+) -> bitcoincore_rpc::Result<T> {
+    debug!("Calling {method} {args:?}");
+    rpc.call(method, args)
+}
+
+// Fetches the transactions `txids` with a single JSON-RPC batch of `getrawtransaction` calls
+// instead of one round trip each, returning them in the order of `txids`. Fails if the batch
+// does (e.g. a proxy in front of the node doesn't accept batches) or any one call in it does.
+pub(crate) fn batch_get_raw_transactions(
+    rpc: &Client,
+    txids: &[Txid],
+) -> bitcoincore_rpc::Result<Vec<Transaction>> {
+    if txids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let client = rpc.get_jsonrpc_client();
+    let params = txids
+        .iter()
+        .map(|txid| Ok([to_raw_value(txid)?]))
+        .collect::<serde_json::Result<Vec<_>>>()?;
+    let requests: Vec<_> = params
+        .iter()
+        .map(|params| client.build_request("getrawtransaction", params))
+        .collect();
+    debug!(
+        "Calling getrawtransaction for {} txids in one batch",
+        txids.len()
+    );
+    client
+        .send_batch(&requests)?
+        .into_iter()
+        .map(|response| {
+            let hex: String = response
+                .ok_or(bitcoincore_rpc::Error::UnexpectedStructure)?
+                .result()?;
+            Ok(deserialize(&Vec::from_hex(&hex)?)?)
+        })
+        .collect()
+}
+
+// Fetches the transactions `txids` with `getrawtransaction`, split over up to `concurrency`
+// threads with a connection each (a client handles one request at a time), and returns them in
+// the order of `txids`.
+pub(crate) fn parallel_get_raw_transactions(
+    node: &Node,
+    txids: &[Txid],
+    concurrency: usize,
+) -> bitcoincore_rpc::Result<Vec<Transaction>> {
+    if txids.is_empty() {
+        return Ok(Vec::new());
+    }
+    // Contiguous chunks keep the order when the results are put back together.
+    let chunk_size = txids.len().div_ceil(concurrency.max(1));
+    debug!(
+        "Calling getrawtransaction for {} txids over {} connection(s)",
+        txids.len(),
+        txids.len().div_ceil(chunk_size)
+    );
+    thread::scope(|scope| {
+        let workers: Vec<_> = txids
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let client = node.base()?;
+                    chunk
+                        .iter()
+                        .map(|txid| client.get_raw_transaction(txid, None))
+                        .collect::<bitcoincore_rpc::Result<Vec<_>>>()
+                })
+            })
+            .collect();
+        let mut txs = Vec::with_capacity(txids.len());
+        for worker in workers {
+            // A worker that panicked takes the caller down with its own panic.
+            let fetched = worker
+                .join()
+                .unwrap_or_else(|panic| panic::resume_unwind(panic));
+            txs.extend(fetched?);
+        }
+        Ok(txs)
+    })
+}
+
+// Subset of the `getmempoolinfo` result.
+#[derive(Debug, Deserialize)]
+pub(crate) struct MempoolInfo {
+    // Number of transactions in the mempool.
+    pub(crate) size: u64,
+    // Sum of the virtual sizes of those transactions.
+    pub(crate) bytes: u64,
+    #[serde(
+        rename = "mempoolminfee",
+        with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc"
+    )]
+    pub(crate) min_fee_per_kvb: Amount,
+}
+
+pub(crate) fn get_mempool_info(rpc: &impl RpcBackend) -> bitcoincore_rpc::Result<MempoolInfo> {
+    rpc_call(rpc, "getmempoolinfo", &[])
+}
+
+// One entry of the `getindexinfo` result.
+#[derive(Debug, Deserialize)]
+pub(crate) struct IndexInfo {
+    pub(crate) synced: bool,
+    pub(crate) best_block_height: u64,
+}
+
+// Returns the optional indexes (txindex, coinstatsindex, ...) the node runs, keyed by name.
+pub(crate) fn get_index_info(
+    rpc: &impl RpcBackend,
+) -> bitcoincore_rpc::Result<HashMap<String, IndexInfo>> {
+    rpc_call(rpc, "getindexinfo", &[])
+}
+
+// Whether the node keeps a transaction index (`-txindex`), without which `getrawtransaction`
+// only finds mempool transactions unless told their block. Nodes too old for `getindexinfo`
+// count as having none.
+pub(crate) fn has_txindex(rpc: &impl RpcBackend) -> bool {
+    get_index_info(rpc).is_ok_and(|indexes| indexes.contains_key("txindex"))
+}
+
+// Subset of the `gettxoutsetinfo` result: a summary of the node's UTXO set.
+#[derive(Debug, Deserialize)]
+pub(crate) struct UtxoSetInfo {
+    pub(crate) height: u64,
+    #[serde(rename = "bestblock")]
+    pub(crate) best_block: BlockHash,
+    // Number of unspent outputs.
+    pub(crate) txouts: u64,
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    pub(crate) total_amount: Amount,
+}
+
+// Summarizes the UTXO set at the chain tip. Read-only, but may take a while on a large chain.
+pub(crate) fn utxo_set_summary(rpc: &impl RpcBackend) -> bitcoincore_rpc::Result<UtxoSetInfo> {
+    rpc_call(rpc, "gettxoutsetinfo", &[])
+}
+
+// Subset of the `analyzepsbt` result.
+#[derive(Debug, Deserialize)]
+pub(crate) struct PsbtAnalysis {
+    #[serde(default)]
+    pub(crate) inputs: Vec<PsbtInputAnalysis>,
+    pub(crate) estimated_vsize: Option<u64>,
+    #[serde(default, with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc::opt")]
+    pub(crate) estimated_feerate: Option<Amount>,
+    #[serde(default, with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc::opt")]
+    pub(crate) fee: Option<Amount>,
+    // Role that has to act next on the whole PSBT: updater, signer, finalizer or extractor.
+    pub(crate) next: String,
+    pub(crate) error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct PsbtInputAnalysis {
+    pub(crate) is_final: bool,
+    pub(crate) next: Option<String>,
+}
+
+pub(crate) fn analyze_psbt(
+    rpc: &impl RpcBackend,
+    psbt: &str,
+) -> bitcoincore_rpc::Result<PsbtAnalysis> {
+    rpc_call(rpc, "analyzepsbt", &[json!(psbt)])
+}
+
+// Subset of the `decodepsbt` result.
+#[derive(Debug, Deserialize)]
+pub(crate) struct DecodedPsbt {
+    pub(crate) tx: DecodedPsbtTx,
+    pub(crate) inputs: Vec<DecodedPsbtInput>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct DecodedPsbtTx {
+    pub(crate) txid: Txid,
+    pub(crate) vin: Vec<DecodedPsbtOutPoint>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct DecodedPsbtOutPoint {
+    pub(crate) txid: Txid,
+    pub(crate) vout: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct DecodedPsbtInput {
+    // Signatures so far, keyed by public key.
+    #[serde(default)]
+    pub(crate) partial_signatures: HashMap<String, String>,
+    pub(crate) taproot_key_path_sig: Option<String>,
+    #[serde(rename = "final_scriptSig")]
+    pub(crate) final_script_sig: Option<Value>,
+    #[serde(rename = "final_scriptwitness")]
+    pub(crate) final_script_witness: Option<Vec<String>>,
+}
+
+pub(crate) fn decode_psbt(
+    rpc: &impl RpcBackend,
+    psbt: &str,
+) -> bitcoincore_rpc::Result<DecodedPsbt> {
+    rpc_call(rpc, "decodepsbt", &[json!(psbt)])
+}
+
+// Bitcoin Core RPC error codes we react to (see src/rpc/protocol.h).
+pub(crate) const RPC_WALLET_ERROR: i32 = -4;
+pub(crate) const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
+pub(crate) const RPC_INVALID_PARAMETER: i32 = -8;
+pub(crate) const RPC_WALLET_INVALID_LABEL_NAME: i32 = -11;
+pub(crate) const RPC_IN_WARMUP: i32 = -28;
+pub(crate) const RPC_WALLET_ALREADY_LOADED: i32 = -35;
+
+// Returns the error reported by the node itself, if `err` is a JSON-RPC error response.
+pub(crate) fn rpc_error(
+    err: &bitcoincore_rpc::Error,
+) -> Option<&bitcoincore_rpc::jsonrpc::error::RpcError> {
+    match err {
+        bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Rpc(rpc_err)) => {
+            Some(rpc_err)
+        }
+        _ => None,
+    }
+}
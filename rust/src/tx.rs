@@ -0,0 +1,1009 @@
+// Building, sending and confirming the Miner -> Trader transaction, and its fee.
+
+use crate::backend::RpcBackend;
+use crate::cli::SendArgs;
+use crate::error::{AppError, RpcContext};
+use crate::interrupt;
+use crate::mining::{
+    coinbase_rewards, ensure_can_mine, generate_block, generate_blocks, mine_until_balance,
+};
+use crate::rpc::{
+    analyze_psbt, decode_psbt, get_mempool_info, rpc_call, rpc_error, RPC_INVALID_ADDRESS_OR_KEY,
+};
+use crate::wallet::{
+    balance_change, check_addr, check_balance_delta, descriptor_address, import_descriptor,
+    list_utxos, owns, prev_transactions, print_utxo_table, BalanceDelta, BalanceSnapshot, Wallets,
+    RECEIVED_LABEL,
+};
+use bitcoin::Psbt;
+use bitcoincore_rpc::bitcoin::consensus::encode::deserialize;
+use bitcoincore_rpc::bitcoin::hex::DisplayHex;
+use bitcoincore_rpc::bitcoin::{
+    Address, Amount, BlockHash, FeeRate, Network, Script, ScriptBuf, Transaction, Txid,
+};
+use bitcoincore_rpc::json::{
+    FundRawTransactionOptions, FundRawTransactionResult, ListUnspentResultEntry,
+    WalletCreateFundedPsbtOptions,
+};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Virtual size assumed for the send when estimating its fee up front: a couple of inputs and
+// two outputs, rounded up.
+const ESTIMATED_SEND_VSIZE: u64 = 250;
+
+// Rough fee for the send at `fee_rate` or, without one, the mempool's minimum fee rate.
+fn estimate_send_fee(
+    rpc: &impl RpcBackend,
+    fee_rate: Option<FeeRate>,
+) -> bitcoincore_rpc::Result<Amount> {
+    let fee_rate = match fee_rate {
+        Some(fee_rate) => fee_rate,
+        None => fee_rate_from_btc_per_kvb(get_mempool_info(rpc)?.min_fee_per_kvb),
+    };
+    fee_rate
+        .fee_vb(ESTIMATED_SEND_VSIZE)
+        .ok_or(bitcoincore_rpc::Error::UnexpectedStructure)
+}
+
+// Picks the send's fee rate: `--fee-rate` if given, otherwise the node's `estimatesmartfee`
+// for `--conf-target` blocks. `None` leaves it to the wallet, which happens when the node has
+// no estimate yet (always the case on a fresh regtest chain).
+fn choose_fee_rate(
+    rpc: &impl RpcBackend,
+    args: &SendArgs,
+) -> bitcoincore_rpc::Result<Option<FeeRate>> {
+    if let Some(fee_rate) = args.fee_rate {
+        info!(
+            "Using a fee rate of {:.3} sat/vB (--fee-rate).",
+            sat_per_vb(fee_rate)
+        );
+        return Ok(Some(fee_rate));
+    }
+    let estimate = rpc.estimate_smart_fee(args.conf_target, None)?;
+    match estimate.fee_rate {
+        Some(fee_per_kvb) => {
+            let fee_rate = fee_rate_from_btc_per_kvb(fee_per_kvb);
+            info!(
+                "Using a fee rate of {:.3} sat/vB, estimated for confirmation within {} blocks.",
+                sat_per_vb(fee_rate),
+                estimate.blocks
+            );
+            Ok(Some(fee_rate))
+        }
+        None => {
+            info!(
+                "The node has no fee estimate ({}); leaving the fee rate to the wallet.",
+                estimate.errors.unwrap_or_default().join("; ")
+            );
+            Ok(None)
+        }
+    }
+}
+
+// Fee rates are quoted per kvB in BTC by the node but per vB in sats on the command line and
+// by `sendtoaddress`/`sendmany`. A vbyte is 4 weight units, so 1 sat/vB is 250 sat/kwu.
+fn fee_rate_from_btc_per_kvb(fee_per_kvb: Amount) -> FeeRate {
+    FeeRate::from_sat_per_kwu(fee_per_kvb.to_sat() / 4)
+}
+
+fn btc_per_kvb(fee_rate: FeeRate) -> Amount {
+    Amount::from_sat(fee_rate.to_sat_per_kwu() * 4)
+}
+
+fn sat_per_vb(fee_rate: FeeRate) -> f64 {
+    fee_rate.to_sat_per_kwu() as f64 / 250.0
+}
+
+// Like `RpcApi::send_to_address`, but with the `fee_rate` argument (Core 21+) that the typed
+// method doesn't expose. Without a fee rate the wallet picks one.
+fn send_to_address_at(
+    wallet: &impl RpcBackend,
+    addr: &Address,
+    amount: Amount,
+    fee_rate: Option<FeeRate>,
+) -> bitcoincore_rpc::Result<Txid> {
+    let args = [
+        json!(addr.to_string()),
+        json!(amount.to_btc()),
+        json!(null),                     // comment
+        json!(null),                     // comment_to
+        json!(null),                     // subtractfeefromamount
+        json!(null),                     // replaceable
+        json!(null),                     // conf_target
+        json!(null),                     // estimate_mode
+        json!(null),                     // avoid_reuse
+        json!(fee_rate.map(sat_per_vb)), // fee rate in sats/vb
+    ];
+    rpc_call(wallet, "sendtoaddress", &args)
+}
+
+// Creates a transaction with only the `payments` outputs and lets `wallet` pick its inputs and
+// add change (`fundrawtransaction`). Nothing is signed, broadcast or locked.
+fn fund_payments(
+    wallet: &impl RpcBackend,
+    payments: &[(Address, Amount)],
+    fee_rate: Option<FeeRate>,
+) -> bitcoincore_rpc::Result<FundRawTransactionResult> {
+    let outputs: HashMap<String, Amount> = payments
+        .iter()
+        .map(|(addr, amount)| (addr.to_string(), *amount))
+        .collect();
+    // Use the hex variant: a transaction without inputs doesn't round-trip through the
+    // consensus decoder.
+    let unfunded = wallet.create_raw_transaction_hex(&[], &outputs, None, None)?;
+    debug!("Unfunded transaction: {unfunded}");
+
+    let options = FundRawTransactionOptions {
+        fee_rate: fee_rate.map(btc_per_kvb),
+        ..Default::default()
+    };
+    let funded = wallet.fund_raw_transaction(unfunded, Some(&options), None)?;
+    debug!(
+        "Funded transaction: fee {}, change output #{}",
+        funded.fee, funded.change_position
+    );
+    Ok(funded)
+}
+
+// Smallest amount an output paying `script_pubkey` may have without being dust: worth less than
+// the fee of spending it at Core's dust relay fee rate (3 sat/vB), which Core's
+// GetDustThreshold computes from the size of the output and of a typical input spending it.
+// Nodes don't relay transactions with dust outputs.
+pub(crate) fn dust_threshold(script_pubkey: &Script) -> Amount {
+    script_pubkey.dust_value()
+}
+
+// Refuses `amount` paid to `script_pubkey` if it is dust, or only warns with `allow_dust`.
+// `output` names it in the message, e.g. "change".
+fn check_dust(
+    output: &str,
+    script_pubkey: &Script,
+    amount: Amount,
+    allow_dust: bool,
+) -> Result<(), AppError> {
+    let threshold = dust_threshold(script_pubkey);
+    if amount >= threshold {
+        return Ok(());
+    }
+    if !allow_dust {
+        return Err(AppError::DustOutput {
+            output: output.to_owned(),
+            amount,
+            threshold,
+        });
+    }
+    warn!("The {output} of {amount} is below the dust threshold of {threshold}.");
+    Ok(())
+}
+
+// Checks the change output the wallet added at `change_position` (-1 for none) to `tx`.
+fn check_change_dust(
+    tx: &Transaction,
+    change_position: i32,
+    allow_dust: bool,
+) -> Result<(), AppError> {
+    match usize::try_from(change_position)
+        .ok()
+        .and_then(|n| tx.output.get(n))
+    {
+        Some(change) => check_dust(
+            "change output",
+            &change.script_pubkey,
+            change.value,
+            allow_dust,
+        ),
+        None => Ok(()),
+    }
+}
+
+// Makes `payments` from `wallet` by building the transaction step by step instead of using
+// `sendtoaddress`: create it with only the payment outputs, let the wallet pick inputs and add
+// change (`fundrawtransaction`), sign it, and broadcast it.
+fn send_with_raw_transaction(
+    wallet: &impl RpcBackend,
+    payments: &[(Address, Amount)],
+    fee_rate: Option<FeeRate>,
+    allow_dust: bool,
+) -> Result<Txid, AppError> {
+    let funded =
+        fund_payments(wallet, payments, fee_rate).context(|| "funding the raw transaction")?;
+    let tx = funded.transaction().map_err(bitcoincore_rpc::Error::from)?;
+    check_change_dust(&tx, funded.change_position, allow_dust)?;
+
+    let signed = wallet
+        .sign_raw_transaction_with_wallet(&funded.hex, None, None)
+        .context(|| "signing the raw transaction")?;
+    if !signed.complete {
+        let errors = signed
+            .errors
+            .unwrap_or_default()
+            .into_iter()
+            .map(|e| e.error)
+            .collect::<Vec<_>>()
+            .join("; ");
+        return Err(AppError::IncompleteSignature(errors));
+    }
+
+    wallet
+        .send_raw_transaction(&signed.hex)
+        .context(|| "broadcasting the raw transaction")
+}
+
+// Makes `payments` from `wallet` through the PSBT lifecycle, logging the result of each stage:
+// the wallet creates and funds a PSBT (`walletcreatefundedpsbt`), signs it
+// (`walletprocesspsbt`), `finalizepsbt` extracts the network transaction, and
+// `sendrawtransaction` broadcasts it.
+fn send_with_psbt(
+    wallet: &impl RpcBackend,
+    payments: &[(Address, Amount)],
+    fee_rate: Option<FeeRate>,
+    allow_dust: bool,
+) -> Result<Txid, AppError> {
+    let outputs: HashMap<String, Amount> = payments
+        .iter()
+        .map(|(addr, amount)| (addr.to_string(), *amount))
+        .collect();
+    let options = WalletCreateFundedPsbtOptions {
+        fee_rate: fee_rate.map(btc_per_kvb),
+        ..Default::default()
+    };
+    let created = wallet
+        .wallet_create_funded_psbt(&[], &outputs, None, Some(options), None)
+        .context(|| "creating the PSBT")?;
+    info!(
+        "Created PSBT (fee {}, change output #{}): {}",
+        created.fee, created.change_position, created.psbt
+    );
+
+    let processed = wallet
+        .wallet_process_psbt(&created.psbt, Some(true), None, None)
+        .context(|| "signing the PSBT")?;
+    info!(
+        "Processed PSBT (complete: {}): {}",
+        processed.complete, processed.psbt
+    );
+    if !processed.complete {
+        return Err(AppError::IncompleteSignature(
+            "walletprocesspsbt left inputs unsigned".to_owned(),
+        ));
+    }
+
+    let finalized = wallet
+        .finalize_psbt(&processed.psbt, Some(true))
+        .context(|| "finalizing the PSBT")?;
+    let hex = match finalized.hex {
+        Some(hex) if finalized.complete => hex,
+        _ => {
+            return Err(AppError::IncompleteSignature(
+                "finalizepsbt could not finalize every input".to_owned(),
+            ))
+        }
+    };
+    info!("Finalized transaction: {}", hex.to_lower_hex_string());
+    let tx: Transaction = deserialize(&hex).map_err(bitcoincore_rpc::Error::from)?;
+    check_change_dust(&tx, created.change_position, allow_dust)?;
+
+    let txid = wallet
+        .send_raw_transaction(&hex)
+        .context(|| "broadcasting the finalized PSBT")?;
+    info!("Broadcast the finalized transaction as {txid}.");
+    Ok(txid)
+}
+
+// What funding a send would look like: the coins the wallet picks, the change it adds (if any)
+// and the fee.
+#[derive(Debug, Clone)]
+pub struct SendPreview {
+    pub inputs: Vec<ListUnspentResultEntry>,
+    pub change: Option<Amount>,
+    pub fee: Amount,
+}
+
+// Funds `payments` from `wallet` like `--manual-tx` would, but only reports the result instead
+// of signing and broadcasting it.
+pub fn preview_send(
+    wallet: &impl RpcBackend,
+    payments: &[(Address, Amount)],
+    fee_rate: Option<FeeRate>,
+) -> Result<SendPreview, AppError> {
+    let funded = fund_payments(wallet, payments, fee_rate)?;
+    let tx = funded.transaction().map_err(bitcoincore_rpc::Error::from)?;
+    let utxos = list_utxos(wallet)?;
+    let mut inputs = Vec::with_capacity(tx.input.len());
+    for input in &tx.input {
+        let prev = input.previous_output;
+        let utxo = utxos
+            .iter()
+            .find(|utxo| utxo.txid == prev.txid && utxo.vout == prev.vout)
+            .ok_or(AppError::OutputNotFound {
+                txid: prev.txid,
+                vout: prev.vout,
+            })?;
+        inputs.push(utxo.clone());
+    }
+    let change = usize::try_from(funded.change_position)
+        .ok()
+        .and_then(|n| tx.output.get(n))
+        .map(|out| out.value);
+    Ok(SendPreview {
+        inputs,
+        change,
+        fee: funded.fee,
+    })
+}
+
+// Prints which of the Miner's coins would fund the send `args` describes, with the change and
+// fee, without sending anything. Without `--to` recipients the trader output is a stand-in of
+// the same size, so no trader address is used up.
+pub fn run_preview(
+    rpc: &impl RpcBackend,
+    wallets: &Wallets<impl RpcBackend>,
+    network: Network,
+    args: &SendArgs,
+) -> Result<(), AppError> {
+    let payments = if args.to.is_empty() {
+        // A P2WSH output is as large as a P2TR one, and nobody can spend one to an empty script.
+        vec![(Address::p2wsh(&ScriptBuf::new(), network), args.send_amount)]
+    } else {
+        args.to
+            .iter()
+            .map(|recipient| {
+                Ok((
+                    check_addr(recipient.address.clone(), network)?,
+                    recipient.amount,
+                ))
+            })
+            .collect::<Result<Vec<_>, AppError>>()?
+    };
+    let fee_rate = choose_fee_rate(rpc, args)?;
+    let preview = preview_send(&wallets.miner, &payments, fee_rate)?;
+    print_utxo_table(&preview.inputs);
+    println!("Send:   {:.8} BTC", args.total_amount().to_btc());
+    match preview.change {
+        Some(change) => println!("Change: {:.8} BTC", change.to_btc()),
+        None => println!("Change: none"),
+    }
+    println!("Fee:    {:.8} BTC", preview.fee.to_btc());
+    Ok(())
+}
+
+// Pays every address in `outputs` its amount from `wallet` in a single transaction, using
+// the `sendmany` RPC. Without a fee rate the wallet picks one.
+fn send_many(
+    wallet: &impl RpcBackend,
+    outputs: &HashMap<Address, Amount>,
+    fee_rate: Option<FeeRate>,
+) -> bitcoincore_rpc::Result<Txid> {
+    let amounts: serde_json::Map<String, Value> = outputs
+        .iter()
+        .map(|(addr, amount)| (addr.to_string(), json!(amount.to_btc())))
+        .collect();
+    let args = [
+        // `sendmany` still takes a dummy account name first; it must be "".
+        json!(""),
+        Value::Object(amounts),
+        json!(null),                     // minconf
+        json!(null),                     // comment
+        json!(null),                     // subtractfeefrom
+        json!(null),                     // replaceable
+        json!(null),                     // conf_target
+        json!(null),                     // estimate_mode
+        json!(fee_rate.map(sat_per_vb)), // fee rate in sats/vb
+    ];
+    rpc_call(wallet, "sendmany", &args)
+}
+
+// Returns the fee paid by `txid`. Uses the `fee` the wallet records for its own sends and
+// only falls back to summing every input's previous output minus every output when the
+// wallet doesn't report one (e.g. the transaction wasn't sent by this wallet).
+pub fn fee_for_tx(wallet: &impl RpcBackend, txid: &Txid) -> Result<Amount, AppError> {
+    let tx_info = wallet.get_transaction(txid, None)?;
+    if let Some(fee) = tx_info.fee {
+        // The wallet reports fees of outgoing transactions as a negative amount.
+        return Ok(Amount::from_sat(fee.to_sat().unsigned_abs()));
+    }
+
+    let tx = tx_info
+        .transaction()
+        .map_err(bitcoincore_rpc::Error::from)?;
+    let prev_txids: Vec<Txid> = tx
+        .input
+        .iter()
+        .map(|input| input.previous_output.txid)
+        .collect();
+    let prev_txs = prev_transactions(wallet, &prev_txids, |missing| {
+        missing
+            .iter()
+            .map(|txid| {
+                wallet
+                    .get_raw_transaction(txid, None)
+                    .context(|| format!("fetching {txid}"))
+            })
+            .collect()
+    })?;
+    let mut inputs = Vec::with_capacity(tx.input.len());
+    for (input, prev_tx) in tx.input.iter().zip(&prev_txs) {
+        let prev_output = prev_tx
+            .output
+            .get(input.previous_output.vout as usize)
+            .ok_or(AppError::OutputNotFound {
+                txid: input.previous_output.txid,
+                vout: input.previous_output.vout,
+            })?;
+        inputs.push(prev_output.value);
+    }
+    let outputs: Vec<Amount> = tx.output.iter().map(|output| output.value).collect();
+    // Outputs can't exceed inputs in a valid transaction, so if they do the inputs were
+    // resolved incorrectly; report it rather than writing a (saturated) zero fee.
+    let excess = compute_fee(&outputs, &inputs);
+    if excess > Amount::ZERO {
+        return Err(AppError::NegativeFee {
+            txid: *txid,
+            excess,
+        });
+    }
+    Ok(compute_fee(&inputs, &outputs))
+}
+
+// Fee of a transaction spending `inputs` and creating `outputs`, i.e. the inputs total minus
+// the outputs total. Never negative and never panics: when the outputs exceed the inputs the
+// fee saturates to zero, and a total beyond the u64 satoshi range saturates to `Amount::MAX`.
+pub fn compute_fee(inputs: &[Amount], outputs: &[Amount]) -> Amount {
+    let total = |amounts: &[Amount]| {
+        amounts.iter().fold(Amount::ZERO, |total, amount| {
+            total.checked_add(*amount).unwrap_or(Amount::MAX)
+        })
+    };
+    total(inputs)
+        .checked_sub(total(outputs))
+        .unwrap_or(Amount::ZERO)
+}
+
+// How often `wait_for_confirmations` re-checks the transaction, and how long it keeps trying.
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_millis(500);
+pub(crate) const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(60);
+// How many times `wait_for_confirmations` re-mines a transaction whose block was reorged out
+// before it gives up.
+const MAX_REORG_RETRIES: u32 = 3;
+
+// Waits until `txid` has at least `target` confirmations, mining the missing blocks to `addr`
+// between polls (with `generateblock` if `use_generateblock`, putting `txid` in the first).
+// Once the target is reached, the confirming block must still be in the active chain; if a
+// reorg orphaned it, the transaction is mined again (up to MAX_REORG_RETRIES times). Fails if
+// the transaction is dropped or conflicted (e.g. replaced), or if the target isn't reached
+// within CONFIRMATION_TIMEOUT.
+fn wait_for_confirmations(
+    wallet: &impl RpcBackend,
+    txid: &Txid,
+    target: u32,
+    addr: &Address,
+    use_generateblock: bool,
+) -> Result<(), AppError> {
+    let deadline = Instant::now() + CONFIRMATION_TIMEOUT;
+    let mut reorgs = 0;
+    loop {
+        let tx_info = match wallet.get_transaction(txid, None) {
+            Ok(tx_info) => tx_info.info,
+            Err(e)
+                if rpc_error(&e)
+                    .is_some_and(|rpc_err| rpc_err.code == RPC_INVALID_ADDRESS_OR_KEY) =>
+            {
+                return Err(AppError::TransactionDropped(*txid));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        // Negative confirmations mean a conflicting transaction was confirmed instead.
+        if tx_info.confirmations < 0 {
+            return Err(AppError::TransactionDropped(*txid));
+        }
+        let mut confirmations = tx_info.confirmations as u32;
+        if confirmations >= target {
+            let Some(block_hash) = tx_info.blockhash else {
+                return Err(AppError::TransactionUnconfirmed(*txid));
+            };
+            // A block that fell out of the active chain reports -1 confirmations.
+            let block = wallet.get_block_info(&block_hash)?;
+            if block.confirmations >= 1 {
+                return Ok(());
+            }
+            reorgs += 1;
+            if reorgs > MAX_REORG_RETRIES {
+                return Err(AppError::ReorgDetected {
+                    height: block.height as u64,
+                    expected: block_hash,
+                    actual: wallet.get_block_hash(block.height as u64)?,
+                });
+            }
+            warn!(
+                "Block {block_hash} confirming {txid} was reorged out of the active chain; \
+                 mining it again ({reorgs}/{MAX_REORG_RETRIES})."
+            );
+            confirmations = 0;
+        }
+        if Instant::now() >= deadline {
+            return Err(AppError::ConfirmationTimeout {
+                txid: *txid,
+                confirmations,
+                target,
+            });
+        }
+        let missing = target - confirmations;
+        debug!("{txid} has {confirmations}/{target} confirmations; mining {missing} block(s)");
+        if use_generateblock && confirmations == 0 {
+            // generateblock mines only what it is given, so the first block needs the send.
+            interrupt::check()?;
+            generate_block(wallet, addr, &[*txid])
+                .map_err(AppError::from)
+                .and_then(|_| generate_blocks(wallet, (missing - 1).into(), addr, true))
+        } else {
+            generate_blocks(wallet, missing.into(), addr, use_generateblock)
+        }
+        .context(|| format!("mining {missing} block(s) to {addr} to confirm {txid}"))?;
+        thread::sleep(CONFIRMATION_POLL_INTERVAL);
+    }
+}
+
+// Logs the mempool entry of the freshly sent `txid`. Someone else may already have mined it,
+// which is fine; it is an error only if the transaction is neither in the mempool nor
+// confirmed.
+fn log_mempool_entry(
+    rpc: &impl RpcBackend,
+    wallet: &impl RpcBackend,
+    txid: &Txid,
+) -> Result<(), AppError> {
+    if rpc.get_raw_mempool()?.contains(txid) {
+        let mempool_entry = wallet.get_mempool_entry(txid)?;
+        info!("Mempool entry for txid {txid}: {mempool_entry:#?}");
+        return Ok(());
+    }
+    let tx_info = wallet.get_transaction(txid, None)?.info;
+    match tx_info.blockhash {
+        Some(block_hash) if tx_info.confirmations > 0 => {
+            info!("{txid} is not in the mempool: it is already confirmed in block {block_hash}.");
+            Ok(())
+        }
+        _ => Err(AppError::TransactionDropped(*txid)),
+    }
+}
+
+// The parts of a mempool entry `watch_mempool` reports changes of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MempoolStatus {
+    // Fee including any prioritisation.
+    fee: Amount,
+    ancestor_count: u64,
+    descendant_count: u64,
+}
+
+// Polls the mempool entry of `txid` every `interval` and logs changes to its fee and its
+// ancestor and descendant counts until the transaction leaves the mempool. Returns the hash of
+// the block that confirmed it; fails if it was replaced or evicted instead.
+fn watch_mempool(
+    wallet: &impl RpcBackend,
+    txid: &Txid,
+    interval: Duration,
+) -> Result<BlockHash, AppError> {
+    info!("Watching {txid} in the mempool every {interval:?} until it is mined.");
+    let mut last = None;
+    loop {
+        match wallet.get_mempool_entry(txid) {
+            Ok(entry) => {
+                let status = MempoolStatus {
+                    fee: entry.fees.modified,
+                    ancestor_count: entry.ancestor_count,
+                    descendant_count: entry.descendant_count,
+                };
+                if last != Some(status) {
+                    info!(
+                        "{txid} in mempool: fee {}, {} ancestor(s), {} descendant(s)",
+                        status.fee, status.ancestor_count, status.descendant_count
+                    );
+                    last = Some(status);
+                }
+            }
+            Err(e)
+                if rpc_error(&e)
+                    .is_some_and(|rpc_err| rpc_err.code == RPC_INVALID_ADDRESS_OR_KEY) =>
+            {
+                // Gone from the mempool: mined, replaced by a conflicting transaction, or
+                // evicted (expiry, size limit, ...).
+                let tx_info = wallet.get_transaction(txid, None)?.info;
+                if let (Some(block_hash), true) = (tx_info.blockhash, tx_info.confirmations > 0) {
+                    info!(
+                        "{txid} was mined in block {block_hash} at height {}.",
+                        tx_info.blockheight.unwrap_or_default()
+                    );
+                    return Ok(block_hash);
+                }
+                if !tx_info.wallet_conflicts.is_empty() {
+                    return Err(AppError::TransactionReplaced {
+                        txid: *txid,
+                        by: tx_info.wallet_conflicts,
+                    });
+                }
+                return Err(AppError::TransactionDropped(*txid));
+            }
+            Err(e) => return Err(e.into()),
+        }
+        interrupt::check()?;
+        thread::sleep(interval);
+    }
+}
+
+// Fails unless `txid` is a wallet transaction that isn't in a block yet, which is what both
+// `bumpfee` and `abandontransaction` require.
+fn ensure_unconfirmed(wallet: &impl RpcBackend, txid: &Txid) -> Result<(), AppError> {
+    let tx_info = wallet
+        .get_transaction(txid, None)
+        .context(|| format!("looking up {txid} in the Miner wallet"))?
+        .info;
+    if tx_info.confirmations > 0 {
+        return Err(AppError::TransactionConfirmed {
+            txid: *txid,
+            confirmations: tx_info.confirmations as u32,
+        });
+    }
+    Ok(())
+}
+
+// Subset of the `bumpfee` result.
+#[derive(Debug, Deserialize)]
+struct BumpFeeResult {
+    txid: Txid,
+    #[serde(
+        rename = "origfee",
+        with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc"
+    )]
+    original_fee: Amount,
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    fee: Amount,
+}
+
+// Replaces the unconfirmed `txid` with a copy paying a higher fee and returns the txid of the
+// replacement.
+pub fn bump_fee(wallet: &impl RpcBackend, txid: &Txid) -> Result<Txid, AppError> {
+    ensure_unconfirmed(wallet, txid)?;
+    let bumped: BumpFeeResult = rpc_call(wallet, "bumpfee", &[json!(txid)])
+        .context(|| format!("bumping the fee of {txid}"))?;
+    info!(
+        "Replaced {txid} (fee {}) with {} (fee {}).",
+        bumped.original_fee, bumped.txid, bumped.fee
+    );
+    Ok(bumped.txid)
+}
+
+// Marks the unconfirmed `txid` as abandoned so the wallet can spend its inputs again. The node
+// refuses while the transaction is still in its mempool.
+pub fn abandon(wallet: &impl RpcBackend, txid: &Txid) -> Result<(), AppError> {
+    ensure_unconfirmed(wallet, txid)?;
+    rpc_call::<Value>(wallet, "abandontransaction", &[json!(txid)])
+        .context(|| format!("abandoning {txid}"))?;
+    info!("Abandoned {txid}.");
+    Ok(())
+}
+
+// The part of the `gettransaction` result the typed client leaves out.
+#[derive(Debug, Deserialize)]
+struct Replacement {
+    replaced_by_txid: Option<Txid>,
+}
+
+// Follows `bumpfee` replacements from `txid` to the transaction that currently stands for it,
+// so a report asked for a bumped transaction describes the one that can actually confirm.
+pub fn follow_replacements(wallet: &impl RpcBackend, txid: &Txid) -> Result<Txid, AppError> {
+    let mut current = *txid;
+    loop {
+        let tx: Replacement = rpc_call(wallet, "gettransaction", &[json!(current)])?;
+        match tx.replaced_by_txid {
+            Some(next) => {
+                info!("{current} was replaced by {next}; following it.");
+                current = next;
+            }
+            None => return Ok(current),
+        }
+    }
+}
+
+// Prints, for each input of `psbt`, whether it is signed and which role has to act on it next,
+// then the estimated fee, size and fee rate and the role the whole PSBT waits for. Read-only.
+pub fn run_analyze_psbt(rpc: &impl RpcBackend, psbt: &Psbt) -> Result<(), AppError> {
+    let psbt = psbt.to_string();
+    let analysis = analyze_psbt(rpc, &psbt)?;
+    let decoded = decode_psbt(rpc, &psbt)?;
+    if let Some(error) = &analysis.error {
+        warn!("analyzepsbt reports an error: {error}");
+    }
+
+    println!("Transaction: {}", decoded.tx.txid);
+    println!("{:<4} {:<68} {:<22} NEXT", "#", "OUTPOINT", "STATUS");
+    for (n, input) in decoded.inputs.iter().enumerate() {
+        let outpoint = decoded
+            .tx
+            .vin
+            .get(n)
+            .map(|vin| format!("{}:{}", vin.txid, vin.vout))
+            .unwrap_or_default();
+        let status = if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+            "finalized".to_owned()
+        } else if input.taproot_key_path_sig.is_some() {
+            "signed (key path)".to_owned()
+        } else if !input.partial_signatures.is_empty() {
+            format!("{} signature(s)", input.partial_signatures.len())
+        } else {
+            "unsigned".to_owned()
+        };
+        let next = match analysis.inputs.get(n) {
+            Some(input) if input.is_final => "-",
+            Some(input) => input.next.as_deref().unwrap_or("-"),
+            None => "?",
+        };
+        println!("{n:<4} {outpoint:<68} {status:<22} {next}");
+    }
+    match analysis.fee {
+        Some(fee) => println!("Estimated fee:      {:.8} BTC", fee.to_btc()),
+        None => println!("Estimated fee:      unknown (input amounts missing)"),
+    }
+    if let Some(vsize) = analysis.estimated_vsize {
+        println!("Estimated vsize:    {vsize} vB");
+    }
+    if let Some(fee_per_kvb) = analysis.estimated_feerate {
+        println!(
+            "Estimated fee rate: {:.3} sat/vB",
+            sat_per_vb(fee_rate_from_btc_per_kvb(fee_per_kvb))
+        );
+    }
+    println!("Next role:          {}", analysis.next);
+    Ok(())
+}
+
+// Result of a send that reached its confirmation target.
+pub struct SendOutcome {
+    pub txid: Txid,
+    // Addresses the transaction pays.
+    pub trader_addresses: Vec<Address>,
+    // How the wallet balances changed from just before the send until it was confirmed.
+    pub balance_delta: BalanceDelta,
+    // Time spent mining and waiting until the transaction had its confirmations.
+    pub confirm_time: Duration,
+}
+
+// Sends `args.send_amount` from the Miner to a new Trader address (or pays the `--to`
+// recipients) and mines blocks to `mining_address` until the transaction has
+// `args.confirmations` confirmations, then checks how the wallet balances changed.
+pub fn run_send<B: RpcBackend>(
+    rpc: &impl RpcBackend,
+    wallets: &Wallets<B>,
+    network: Network,
+    args: &SendArgs,
+    mining_address: &Address,
+) -> Result<SendOutcome, AppError> {
+    // Every confirmation but the one --watch waits for is mined here.
+    if !(args.watch && args.confirmations == 1) {
+        ensure_can_mine(network)?;
+    }
+    let payments = if args.to.is_empty() {
+        // Load Trader wallet and generate a new address
+        // 1. Generate a receiving address for Trader with label "Received"
+        let trader_address = match &args.trader_descriptor {
+            Some(descriptor) => {
+                import_descriptor(&wallets.trader, descriptor)?;
+                descriptor_address(&wallets.trader, descriptor, network)?
+            }
+            None => match wallets
+                .trader
+                .get_new_address(Some(RECEIVED_LABEL), Some(args.address_type))
+            {
+                Ok(address) => check_addr(address, network)?,
+                // A watch-only or blank wallet has nothing to derive addresses from.
+                Err(e) if !wallets.trader.get_wallet_info()?.private_keys_enabled => {
+                    return Err(AppError::Config(format!(
+                        "the trader wallet has no private keys and can't generate an address \
+                         ({e}); pass --trader-descriptor or pay it with --to instead"
+                    )));
+                }
+                Err(e) => return Err(e.into()),
+            },
+        };
+        info!("Trader's receiving address: {trader_address}");
+        vec![(trader_address, args.send_amount)]
+    } else {
+        let mut payments = Vec::with_capacity(args.to.len());
+        for recipient in &args.to {
+            let address = check_addr(recipient.address.clone(), network)?;
+            if payments.iter().any(|(a, _)| a == &address) {
+                return Err(AppError::Config(format!(
+                    "--to {address} is given more than once"
+                )));
+            }
+            payments.push((address, recipient.amount));
+        }
+        payments
+    };
+
+    for (address, amount) in &payments {
+        check_dust(
+            &format!("payment to {address}"),
+            &address.script_pubkey(),
+            *amount,
+            args.allow_dust,
+        )?;
+    }
+
+    // 2. Send 20 BTC (or `--send-amount`, or the `--to` amounts) from Miner to Trader
+    let total = args.total_amount();
+    let fee_rate = choose_fee_rate(rpc, args).context(|| "choosing a fee rate")?;
+    let need = total + estimate_send_fee(rpc, fee_rate).context(|| "estimating the fee")?;
+    let available = wallets
+        .miner
+        .get_balance(None, None)
+        .context(|| "querying the Miner's balance")?;
+    if need > available {
+        if !args.auto_fund {
+            warn!(
+                "The Miner needs {} more to cover the send and its fee; pass --auto-fund to mine it.",
+                need - available
+            );
+            return Err(AppError::InsufficientFunds {
+                have: available,
+                need,
+            });
+        }
+        ensure_can_mine(network)?;
+        info!(
+            "The Miner is short by {}; mining until its balance reaches {need}.",
+            need - available
+        );
+        let mining =
+            mine_until_balance(&wallets.miner, mining_address, need, args.use_generateblock)
+                .context(|| format!("mining to address {mining_address}"))?;
+        info!(
+            "Mined {} more block(s); Miner wallet balance: {} BTC",
+            mining.block_hashes.len(),
+            mining.final_balance.to_btc()
+        );
+    }
+    // The send isn't confirmed yet, so count unconfirmed coins (see BalanceSnapshot::take).
+    let before = BalanceSnapshot::take(wallets, 0)?;
+    let start_height = rpc.get_block_count()?;
+    let txid = match payments.as_slice() {
+        _ if args.manual_tx => {
+            send_with_raw_transaction(&wallets.miner, &payments, fee_rate, args.allow_dust)?
+        }
+        _ if args.psbt => send_with_psbt(&wallets.miner, &payments, fee_rate, args.allow_dust)?,
+        // sendtoaddress and sendmany never add dust change: the wallet leaves it to the fee.
+        [(trader_address, amount)] => {
+            send_to_address_at(&wallets.miner, trader_address, *amount, fee_rate)
+                .context(|| format!("sending {amount} to {trader_address}"))?
+        }
+        // Several recipients share one transaction with an output each.
+        _ => send_many(
+            &wallets.miner,
+            &payments.iter().cloned().collect(),
+            fee_rate,
+        )
+        .context(|| format!("sending {total} to {} recipients", payments.len()))?,
+    };
+    info!(
+        "Sent {} BTC from Miner to {} recipient(s). Transaction ID: {txid}",
+        total.to_btc(),
+        payments.len()
+    );
+
+    // Check transaction in mempool
+    // 1. Fetch the unconfirmed transaction from the mempool and print the result
+    log_mempool_entry(rpc, &wallets.miner, &txid)?;
+    let mempool_info = get_mempool_info(rpc)?;
+    debug!(
+        "Mempool holds {} transaction(s), {} vbytes, min fee {}/kvB",
+        mempool_info.size, mempool_info.bytes, mempool_info.min_fee_per_kvb
+    );
+
+    // 2. Mine 1 block (or `--confirmations` blocks) to confirm the transaction. With `--watch`,
+    // wait for someone else to mine the first one.
+    if args.watch {
+        watch_mempool(&wallets.miner, &txid, args.watch_interval)?;
+    }
+    let confirm_start = Instant::now();
+    wait_for_confirmations(
+        &wallets.miner,
+        &txid,
+        args.confirmations,
+        mining_address,
+        args.use_generateblock,
+    )?;
+    let confirm_time = confirm_start.elapsed();
+    info!(
+        "Transaction confirmed with {} confirmation(s).",
+        args.confirmations
+    );
+
+    // 3. Check where the coins went. The Miner pays the payments and the fee but also earns
+    // the rewards of the blocks just mined (which include that fee); each wallet receives the
+    // payments to its own addresses.
+    let fee = fee_for_tx(&wallets.miner, &txid)?;
+    let rewards = coinbase_rewards(
+        &wallets.miner,
+        start_height + 1..=rpc.get_block_count()?,
+        network,
+    )?;
+    let received_by = |wallet: &B| -> Amount {
+        payments
+            .iter()
+            .filter(|(address, _)| owns(wallet, address))
+            .map(|(_, amount)| *amount)
+            .sum()
+    };
+    let expected = BalanceDelta {
+        miner: balance_change(total + fee, rewards + received_by(&wallets.miner)),
+        trader: balance_change(Amount::ZERO, received_by(&wallets.trader)),
+    };
+    let balance_delta = BalanceSnapshot::take(wallets, 1)?.delta_since(&before);
+    check_balance_delta(&expected, &balance_delta)?;
+
+    Ok(SendOutcome {
+        txid,
+        trader_addresses: payments.into_iter().map(|(address, _)| address).collect(),
+        balance_delta,
+        confirm_time,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn btc(amount: f64) -> Amount {
+        Amount::from_btc(amount).unwrap()
+    }
+
+    #[test]
+    fn fee_single_input_two_outputs() {
+        let fee = compute_fee(&[btc(50.0)], &[btc(20.0), btc(29.9999859)]);
+        assert_eq!(fee, Amount::from_sat(1410));
+    }
+
+    #[test]
+    fn fee_multiple_inputs() {
+        let fee = compute_fee(
+            &[btc(50.0), btc(50.0), btc(0.5)],
+            &[btc(80.0), btc(20.4999)],
+        );
+        assert_eq!(fee, Amount::from_sat(10_000));
+    }
+
+    #[test]
+    fn fee_zero_when_outputs_equal_inputs() {
+        let fee = compute_fee(&[btc(50.0)], &[btc(20.0), btc(30.0)]);
+        assert_eq!(fee, Amount::ZERO);
+    }
+
+    #[test]
+    fn fee_saturates_to_zero_when_outputs_exceed_inputs() {
+        let fee = compute_fee(&[btc(20.0)], &[btc(20.0), btc(0.0001)]);
+        assert_eq!(fee, Amount::ZERO);
+    }
+
+    #[test]
+    fn fee_of_no_inputs_or_outputs_is_zero() {
+        assert_eq!(compute_fee(&[], &[]), Amount::ZERO);
+    }
+
+    #[test]
+    fn dust_threshold_follows_core_per_script_type() {
+        let script = |hex: &str| ScriptBuf::from_hex(hex).unwrap();
+        let p2pkh = script(&format!("76a914{}88ac", "00".repeat(20)));
+        let p2wpkh = script(&format!("0014{}", "00".repeat(20)));
+        let p2tr = script(&format!("5120{}", "00".repeat(32)));
+        assert_eq!(dust_threshold(&p2pkh), Amount::from_sat(546));
+        assert_eq!(dust_threshold(&p2wpkh), Amount::from_sat(294));
+        assert_eq!(dust_threshold(&p2tr), Amount::from_sat(330));
+        assert!(check_dust("payment", &p2tr, Amount::from_sat(329), false).is_err());
+        assert!(check_dust("payment", &p2tr, Amount::from_sat(329), true).is_ok());
+        assert!(check_dust("payment", &p2tr, Amount::from_sat(330), false).is_ok());
+    }
+}
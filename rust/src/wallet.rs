@@ -0,0 +1,638 @@
+// Loading or creating the wallets, their addresses and their balances.
+
+use crate::backend::RpcBackend;
+use crate::cli::{GlobalArgs, WalletOptions};
+use crate::config::Node;
+use crate::error::{AppError, RpcContext};
+use crate::rpc::{
+    has_txindex, rpc_call, rpc_error, RPC_INVALID_ADDRESS_OR_KEY, RPC_WALLET_ALREADY_LOADED,
+    RPC_WALLET_ERROR, RPC_WALLET_INVALID_LABEL_NAME,
+};
+use bitcoincore_rpc::bitcoin::address::NetworkUnchecked;
+use bitcoincore_rpc::bitcoin::{Address, Amount, Network, SignedAmount, Transaction, Txid};
+use bitcoincore_rpc::json::{
+    GetTransactionResultDetailCategory, ListTransactionResult, ListUnspentResultEntry,
+};
+use bitcoincore_rpc::Client;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+// Subset of the `getdescriptorinfo` result.
+#[derive(Debug, Deserialize)]
+struct DescriptorInfo {
+    checksum: String,
+    // Whether the descriptor uses a `*` range.
+    #[serde(rename = "isrange")]
+    is_range: bool,
+}
+
+// Returns `descriptor` with its `#checksum` appended, as `importdescriptors` and
+// `deriveaddresses` require, along with whether it is ranged.
+fn checksummed_descriptor(
+    rpc: &impl RpcBackend,
+    descriptor: &str,
+) -> bitcoincore_rpc::Result<(String, DescriptorInfo)> {
+    let bare = descriptor.split('#').next().unwrap_or_default();
+    let info: DescriptorInfo = rpc_call(rpc, "getdescriptorinfo", &[json!(bare)])?;
+    Ok((format!("{bare}#{}", info.checksum), info))
+}
+
+// Label of the Trader's receiving addresses.
+pub(crate) const RECEIVED_LABEL: &str = "Received";
+
+// One entry of the `importdescriptors` result.
+#[derive(Debug, Deserialize)]
+struct ImportDescriptorResult {
+    success: bool,
+    #[serde(default)]
+    warnings: Vec<String>,
+    error: Option<bitcoincore_rpc::jsonrpc::error::RpcError>,
+}
+
+// Imports `descriptor` into `wallet` so it watches (and, with private keys, can spend) its
+// addresses. Fails unless the node reports success. A descriptor without private keys can only
+// go into a wallet created without them (`--trader-watch-only`).
+pub(crate) fn import_descriptor(
+    wallet: &impl RpcBackend,
+    descriptor: &str,
+) -> Result<(), AppError> {
+    let (descriptor, info) = checksummed_descriptor(wallet, descriptor)?;
+    let mut request = json!({ "desc": descriptor, "timestamp": "now" });
+    // Labels are only allowed on descriptors for a single address.
+    if !info.is_range {
+        request["label"] = json!(RECEIVED_LABEL);
+    }
+    let results: Vec<ImportDescriptorResult> =
+        rpc_call(wallet, "importdescriptors", &[json!([request])])?;
+    for result in results {
+        for warning in &result.warnings {
+            warn!("Importing {descriptor}: {warning}");
+        }
+        if !result.success {
+            let reason = result.error.map(|e| e.message).unwrap_or_default();
+            return Err(AppError::DescriptorImport { descriptor, reason });
+        }
+    }
+    Ok(())
+}
+
+// The first address of `descriptor` (index 0 if it is ranged), so the same descriptor always
+// gives the same address.
+pub(crate) fn descriptor_address(
+    rpc: &impl RpcBackend,
+    descriptor: &str,
+    network: Network,
+) -> Result<Address, AppError> {
+    let (descriptor, info) = checksummed_descriptor(rpc, descriptor)?;
+    let mut args = vec![json!(descriptor)];
+    if info.is_range {
+        args.push(json!([0, 0]));
+    }
+    let addresses: Vec<Address<NetworkUnchecked>> = rpc_call(rpc, "deriveaddresses", &args)?;
+    let address = addresses
+        .into_iter()
+        .next()
+        .ok_or(bitcoincore_rpc::Error::UnexpectedStructure)?;
+    check_addr(address, network)
+}
+
+// Outcome of bringing a wallet up with `load_or_create_wallet`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalletState {
+    // The wallet did not exist and was created (and loaded) by this call.
+    Created,
+    // The wallet existed on disk and was loaded by this call.
+    Loaded,
+    // The wallet was already loaded on the node; nothing was done.
+    AlreadyLoaded,
+}
+
+// Makes sure the wallet `name` exists and is loaded on the node. A wallet that is already
+// loaded is left untouched, an existing-but-unloaded wallet is loaded, and a missing one is
+// created with `options`. On Core 23+ new wallets are descriptor wallets.
+pub fn load_or_create_wallet(
+    rpc: &impl RpcBackend,
+    name: &str,
+    options: &WalletOptions,
+) -> bitcoincore_rpc::Result<WalletState> {
+    if rpc.list_wallets()?.iter().any(|loaded| loaded == name) {
+        return Ok(WalletState::AlreadyLoaded);
+    }
+    match rpc.create_wallet(
+        name,
+        Some(options.disable_private_keys),
+        Some(options.blank),
+        options.passphrase.as_deref(),
+        Some(options.avoid_reuse),
+    ) {
+        Ok(_) => Ok(WalletState::Created),
+        // The wallet exists on disk (or was loaded by someone else since `listwallets`).
+        Err(e)
+            if rpc_error(&e).is_some_and(|rpc_err| {
+                rpc_err.code == RPC_WALLET_ERROR && rpc_err.message.contains("already exists")
+            }) =>
+        {
+            match rpc.load_wallet(name) {
+                Ok(_) => Ok(WalletState::Loaded),
+                Err(e)
+                    if rpc_error(&e).is_some_and(|rpc_err| {
+                        rpc_err.code == RPC_WALLET_ALREADY_LOADED
+                            || rpc_err.message.contains("is already loaded")
+                    }) =>
+                {
+                    Ok(WalletState::AlreadyLoaded)
+                }
+                Err(e) => Err(e),
+            }
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// Checks that an address reported by the node belongs to `network`.
+pub fn check_addr(a: Address<NetworkUnchecked>, network: Network) -> Result<Address, AppError> {
+    if a.is_valid_for_network(network) {
+        Ok(a.assume_checked())
+    } else {
+        Err(AppError::WrongNetwork {
+            address: a.assume_checked().to_string(),
+            expected: network,
+        })
+    }
+}
+
+// Whether `addr` belongs to `wallet`. Descriptor wallets report `ismine` for every address of
+// their descriptors, with or without private keys, so a watch-only (disable_private_keys)
+// Trader still owns its addresses. Legacy wallets report watch-only addresses as
+// `iswatchonly` instead, which counts too.
+pub fn owns(wallet: &impl RpcBackend, addr: &Address) -> bool {
+    match wallet.get_address_info(addr) {
+        Ok(info) => info.is_mine.unwrap_or(false) || info.is_watchonly.unwrap_or(false),
+        Err(e) => {
+            debug!("Could not look up address {addr}: {e}");
+            false
+        }
+    }
+}
+
+// The Miner and Trader wallets, each with a client bound to its `/wallet/<name>` endpoint,
+// and the node they live on for calls that need connections of their own.
+pub struct Wallets<B: RpcBackend = Client> {
+    pub miner: B,
+    pub trader: B,
+    // None when the wallets aren't on a real node (the mock backend in tests), in which case
+    // nothing opens connections of its own.
+    pub(crate) node: Option<Node>,
+    // How many connections to the node may be used at once.
+    pub(crate) rpc_concurrency: usize,
+}
+
+// Creates or loads both wallets. Have logic to optionally create/load them if they do not
+// exist or not loaded already.
+pub fn open_wallets(
+    rpc: &impl RpcBackend,
+    node: &Node,
+    global: &GlobalArgs,
+) -> Result<Wallets, AppError> {
+    // --- Wallet Creation/Loading ---
+    let miner_options = WalletOptions::default();
+    for (wallet_name, options) in [
+        (global.miner_wallet.as_str(), &miner_options),
+        (global.trader_wallet.as_str(), &global.trader_wallet_options),
+    ] {
+        match load_or_create_wallet(rpc, wallet_name, options)
+            .context(|| format!("loading or creating wallet '{wallet_name}'"))?
+        {
+            WalletState::Created => info!("Wallet '{wallet_name}' created."),
+            WalletState::Loaded => info!("Wallet '{wallet_name}' loaded."),
+            WalletState::AlreadyLoaded => info!("Wallet '{wallet_name}' already loaded."),
+        }
+    }
+    // Instantiate Client objects for each wallet using wallet-specific URL
+    let miner = node.wallet(&global.miner_wallet)?;
+    let trader = node.wallet(&global.trader_wallet)?;
+    Ok(Wallets {
+        miner,
+        trader,
+        node: Some(node.clone()),
+        rpc_concurrency: global.rpc_concurrency as usize,
+    })
+}
+
+// Every unspent output of `wallet`, including unconfirmed ones. Immature coinbase outputs
+// aren't listed until they can be spent.
+pub fn list_utxos(
+    wallet: &impl RpcBackend,
+) -> bitcoincore_rpc::Result<Vec<ListUnspentResultEntry>> {
+    wallet.list_unspent(Some(0), None, None, None, None)
+}
+
+// Prints `utxos` as a table, one output per line, followed by their total.
+pub fn print_utxo_table(utxos: &[ListUnspentResultEntry]) {
+    println!(
+        "{:<68} {:>17} {:>6}  ADDRESS",
+        "OUTPOINT", "AMOUNT (BTC)", "CONFS"
+    );
+    for utxo in utxos {
+        let address = utxo
+            .address
+            .as_ref()
+            .map(|a| a.assume_checked_ref().to_string())
+            .unwrap_or_default();
+        println!(
+            "{:<68} {:>17.8} {:>6}  {address}",
+            format!("{}:{}", utxo.txid, utxo.vout),
+            utxo.amount.to_btc(),
+            utxo.confirmations
+        );
+    }
+    let total = utxos.iter().map(|utxo| utxo.amount).sum::<Amount>();
+    println!(
+        "{} output(s), {:.8} BTC in total",
+        utxos.len(),
+        total.to_btc()
+    );
+}
+
+// The `count` most recent transactions of `wallet` after leaving out the `skip` most recent
+// ones, newest first. A transaction that touches several of the wallet's addresses is listed
+// once per address (`listtransactions` returns one entry per wallet output or input).
+pub fn recent_transactions(
+    wallet: &impl RpcBackend,
+    count: usize,
+    skip: usize,
+) -> bitcoincore_rpc::Result<Vec<ListTransactionResult>> {
+    // "*" is every label; the node lists the transactions oldest first.
+    let mut txs = wallet.list_transactions(Some("*"), Some(count), Some(skip), Some(true))?;
+    txs.reverse();
+    Ok(txs)
+}
+
+fn category_name(category: &GetTransactionResultDetailCategory) -> &'static str {
+    match category {
+        GetTransactionResultDetailCategory::Send => "send",
+        GetTransactionResultDetailCategory::Receive => "receive",
+        GetTransactionResultDetailCategory::Generate => "generate",
+        GetTransactionResultDetailCategory::Immature => "immature",
+        GetTransactionResultDetailCategory::Orphan => "orphan",
+    }
+}
+
+// Prints `txs` as a table, one entry per line: what it did to the wallet, by how much, how
+// deeply it is confirmed (negative for a conflicted one) and its txid.
+pub fn print_transaction_table(txs: &[ListTransactionResult]) {
+    println!(
+        "{:<8} {:>17} {:>6}  TXID",
+        "CATEGORY", "AMOUNT (BTC)", "CONFS"
+    );
+    for tx in txs {
+        println!(
+            "{:<8} {:>17.8} {:>6}  {}",
+            category_name(&tx.detail.category),
+            tx.detail.amount.to_btc(),
+            tx.info.confirmations,
+            tx.info.txid
+        );
+    }
+}
+
+// How much the addresses of `wallet` labelled `label` have received in transactions with at
+// least `min_conf` confirmations (`getreceivedbylabel`), leaving out what they have spent. A
+// label no address carries has received nothing.
+pub fn received_by_label(
+    wallet: &impl RpcBackend,
+    label: &str,
+    min_conf: u32,
+) -> Result<Amount, AppError> {
+    let received: f64 = match rpc_call(
+        wallet,
+        "getreceivedbylabel",
+        &[json!(label), json!(min_conf)],
+    ) {
+        Ok(received) => received,
+        Err(e) if rpc_error(&e).is_some_and(|e| e.code == RPC_WALLET_INVALID_LABEL_NAME) => {
+            return Ok(Amount::ZERO);
+        }
+        Err(e) => return Err(e).context(|| format!("summing what label '{label}' received")),
+    };
+    Ok(Amount::from_btc(received).map_err(bitcoincore_rpc::Error::InvalidAmount)?)
+}
+
+// The addresses of `wallet` labelled `label` (`getaddressesbylabel`), sorted. A label no
+// address carries yields none rather than an error.
+pub fn addresses_by_label(
+    wallet: &impl RpcBackend,
+    label: &str,
+    network: Network,
+) -> Result<Vec<Address>, AppError> {
+    // The result maps each address to its purpose ("send" or "receive").
+    let by_address: HashMap<Address<NetworkUnchecked>, Value> =
+        match rpc_call(wallet, "getaddressesbylabel", &[json!(label)]) {
+            Ok(by_address) => by_address,
+            Err(e) if rpc_error(&e).is_some_and(|e| e.code == RPC_WALLET_INVALID_LABEL_NAME) => {
+                return Ok(Vec::new());
+            }
+            Err(e) => {
+                return Err(e).context(|| format!("listing the addresses labelled '{label}'"))
+            }
+        };
+    let mut addresses = by_address
+        .into_keys()
+        .map(|address| check_addr(address, network))
+        .collect::<Result<Vec<_>, _>>()?;
+    addresses.sort_by_cached_key(Address::to_string);
+    Ok(addresses)
+}
+
+// The transaction `txid` as `wallet` recorded it, or None if it isn't one of the wallet's.
+pub(crate) fn wallet_transaction(
+    wallet: &impl RpcBackend,
+    txid: &Txid,
+) -> Result<Option<Transaction>, AppError> {
+    match wallet.get_transaction(txid, None) {
+        Ok(tx_info) => Ok(Some(
+            tx_info
+                .transaction()
+                .map_err(bitcoincore_rpc::Error::from)?,
+        )),
+        Err(e) if rpc_error(&e).is_some_and(|e| e.code == RPC_INVALID_ADDRESS_OR_KEY) => Ok(None),
+        Err(e) => Err(e).context(|| format!("looking up {txid} in the wallet")),
+    }
+}
+
+// Looks each of `txids` up with `from_wallet` first and hands only those it doesn't know to
+// `from_node`, in a single call, returning the results in the order of `txids`.
+fn wallet_first<T>(
+    txids: &[Txid],
+    from_wallet: impl FnMut(&Txid) -> Result<Option<T>, AppError>,
+    from_node: impl FnOnce(&[Txid]) -> Result<Vec<T>, AppError>,
+) -> Result<Vec<T>, AppError> {
+    let found = txids
+        .iter()
+        .map(from_wallet)
+        .collect::<Result<Vec<_>, _>>()?;
+    let missing: Vec<Txid> = txids
+        .iter()
+        .zip(&found)
+        .filter(|(_, tx)| tx.is_none())
+        .map(|(txid, _)| *txid)
+        .collect();
+    let fetched = if missing.is_empty() {
+        Vec::new()
+    } else {
+        from_node(&missing)?
+    };
+    if fetched.len() != missing.len() {
+        return Err(bitcoincore_rpc::Error::UnexpectedStructure).context(|| {
+            format!(
+                "fetching {} transaction(s) from the node, which returned {}",
+                missing.len(),
+                fetched.len()
+            )
+        });
+    }
+    let mut fetched = fetched.into_iter();
+    Ok(found
+        .into_iter()
+        .filter_map(|tx| tx.or_else(|| fetched.next()))
+        .collect())
+}
+
+// The transactions `txids` that inputs spend, in order. The wallet knows every transaction
+// that paid it, the Miner's coinbases included, whether or not the node keeps a transaction
+// index, so it is asked first. `from_node` (`getrawtransaction`) only fetches the rest, which
+// takes a transaction index (see has_txindex); without one they fail with TxindexRequired
+// instead of `getrawtransaction`'s "No such mempool transaction".
+pub(crate) fn prev_transactions(
+    wallet: &impl RpcBackend,
+    txids: &[Txid],
+    from_node: impl FnOnce(&[Txid]) -> Result<Vec<Transaction>, AppError>,
+) -> Result<Vec<Transaction>, AppError> {
+    wallet_first(
+        txids,
+        |txid| wallet_transaction(wallet, txid),
+        |missing| {
+            debug!(
+                "{} of {} transaction(s) aren't the wallet's; asking the node",
+                missing.len(),
+                txids.len()
+            );
+            if has_txindex(wallet) {
+                return from_node(missing);
+            }
+            Err(AppError::TxindexRequired {
+                txid: missing[0],
+                pruned: wallet.get_blockchain_info()?.pruned,
+            })
+        },
+    )
+}
+
+// Prints the addresses of the Miner and Trader wallets grouped by label, so it shows which
+// addresses the runs so far have generated and under which label.
+pub fn print_addresses_by_label(
+    wallets: &Wallets<impl RpcBackend>,
+    network: Network,
+) -> Result<(), AppError> {
+    for (name, wallet) in [("Miner", &wallets.miner), ("Trader", &wallets.trader)] {
+        let mut labels: Vec<String> = rpc_call(wallet, "listlabels", &[])
+            .context(|| format!("listing the labels of the {name} wallet"))?;
+        labels.sort();
+        println!("{name} wallet:");
+        if labels.is_empty() {
+            println!("  no labelled addresses");
+        }
+        for label in labels {
+            let addresses = addresses_by_label(wallet, &label, network)?;
+            let shown = if label.is_empty() {
+                "(no label)"
+            } else {
+                &label
+            };
+            println!("  {shown} ({} address(es))", addresses.len());
+            for address in addresses {
+                println!("    {address}");
+            }
+        }
+    }
+    Ok(())
+}
+
+// Balances of the Miner and Trader wallets at one point of a run. Immature coinbase rewards
+// are included, so maturing coinbases don't change a snapshot; only transactions and newly
+// mined blocks do.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct BalanceSnapshot {
+    miner: Amount,
+    trader: Amount,
+}
+
+impl BalanceSnapshot {
+    // Counts coins with at least `min_conf` confirmations. A snapshot taken before the
+    // confirming block needs `min_conf` 0 to see unconfirmed coins at all; one taken after it
+    // should use 1, so transactions still sitting in the mempool are left out.
+    pub(crate) fn take(
+        wallets: &Wallets<impl RpcBackend>,
+        min_conf: usize,
+    ) -> bitcoincore_rpc::Result<Self> {
+        Ok(BalanceSnapshot {
+            miner: wallet_balance(&wallets.miner, min_conf)?,
+            trader: wallet_balance(&wallets.trader, min_conf)?,
+        })
+    }
+
+    // How the balances changed from `before` to this snapshot.
+    pub(crate) fn delta_since(&self, before: &BalanceSnapshot) -> BalanceDelta {
+        BalanceDelta {
+            miner: balance_change(before.miner, self.miner),
+            trader: balance_change(before.trader, self.trader),
+        }
+    }
+}
+
+// Net flow into (positive) or out of (negative) each wallet between two snapshots.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BalanceDelta {
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    pub miner: SignedAmount,
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    pub trader: SignedAmount,
+}
+
+// A wallet's own coins split by whether they can be spent yet.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BalanceBreakdown {
+    // Confirmed coins, and unconfirmed change from the wallet's own transactions: spendable.
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    pub trusted: Amount,
+    // Unconfirmed payments from other wallets.
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    pub untrusted_pending: Amount,
+    // Coinbase rewards with fewer than COINBASE_MATURITY confirmations.
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    pub immature: Amount,
+}
+
+// Splits the balance of `wallet` into spendable, pending and immature coins (`getbalances`).
+pub fn balance_breakdown(wallet: &impl RpcBackend) -> bitcoincore_rpc::Result<BalanceBreakdown> {
+    let balances = wallet.get_balances()?;
+    Ok(BalanceBreakdown {
+        trusted: balances.mine.trusted,
+        untrusted_pending: balances.mine.untrusted_pending,
+        immature: balances.mine.immature,
+    })
+}
+
+// Balance of `wallet` counting coins with at least `min_conf` confirmations, plus immature
+// coinbase rewards. `getbalance` leaves out unconfirmed payments from other wallets even with
+// minconf 0, so that case is summed from `getbalances` instead.
+fn wallet_balance(wallet: &impl RpcBackend, min_conf: usize) -> bitcoincore_rpc::Result<Amount> {
+    let balances = balance_breakdown(wallet)?;
+    let spendable = if min_conf == 0 {
+        balances.trusted + balances.untrusted_pending
+    } else {
+        wallet.get_balance(Some(min_conf), None)?
+    };
+    Ok(spendable + balances.immature)
+}
+
+// Difference between two balances, which may be negative.
+pub(crate) fn balance_change(before: Amount, after: Amount) -> SignedAmount {
+    SignedAmount::from_sat(after.to_sat() as i64 - before.to_sat() as i64)
+}
+
+// Checks that both balances changed by exactly what `expected` says.
+pub(crate) fn check_balance_delta(
+    expected: &BalanceDelta,
+    actual: &BalanceDelta,
+) -> Result<(), AppError> {
+    for (name, expected, actual) in [
+        ("Miner", expected.miner, actual.miner),
+        ("Trader", expected.trader, actual.trader),
+    ] {
+        if actual != expected {
+            return Err(AppError::BalanceMismatch {
+                wallet: name.to_owned(),
+                expected,
+                actual,
+            });
+        }
+        debug!("Balance of '{name}' changed by {actual} as expected");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn txid(n: u8) -> Txid {
+        format!("{n:064x}").parse().unwrap()
+    }
+
+    #[test]
+    fn wallet_first_skips_the_node_when_the_wallet_knows_everything() {
+        let txids = [txid(1), txid(2)];
+        let found = wallet_first(
+            &txids,
+            |txid| Ok(Some(txid.to_string())),
+            |_| panic!("the node should not be asked"),
+        )
+        .unwrap();
+        assert_eq!(found, [txid(1).to_string(), txid(2).to_string()]);
+    }
+
+    #[test]
+    fn wallet_first_asks_the_node_only_for_the_rest_in_order() {
+        let txids = [txid(1), txid(2), txid(3), txid(4)];
+        let found = wallet_first(
+            &txids,
+            |txid| Ok((*txid == txids[1]).then(|| "wallet".to_owned())),
+            |missing| {
+                assert_eq!(missing, [txid(1), txid(3), txid(4)]);
+                Ok(missing.iter().map(|txid| format!("node {txid}")).collect())
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            found,
+            [
+                format!("node {}", txid(1)),
+                "wallet".to_owned(),
+                format!("node {}", txid(3)),
+                format!("node {}", txid(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn wallet_first_fails_when_the_node_cannot_help() {
+        let err = wallet_first(
+            &[txid(7)],
+            |_| Ok(None::<String>),
+            |missing| {
+                Err(AppError::TxindexRequired {
+                    txid: missing[0],
+                    pruned: false,
+                })
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, AppError::TxindexRequired { txid: t, .. } if t == txid(7)));
+    }
+
+    #[test]
+    fn wallet_first_fails_when_the_node_returns_too_few() {
+        let err = wallet_first(
+            &[txid(1), txid(2)],
+            |_| Ok(None::<String>),
+            |_| Ok(vec!["only one".to_owned()]),
+        )
+        .unwrap_err();
+        assert!(err.to_string().starts_with(
+            "RPC error while fetching 2 transaction(s) from the node, which returned 1"
+        ));
+    }
+}
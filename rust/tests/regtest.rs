@@ -0,0 +1,161 @@
+// End-to-end tests against a regtest bitcoind. The node is found the same way the binary finds
+// it (BITCOIN_RPC_URL, BITCOIN_RPC_USER, BITCOIN_RPC_PASS); each test is skipped when no regtest
+// node answers there.
+
+use bitcoincore_rpc::bitcoin::{Address, Amount, Network};
+use bitcoincore_rpc::json::AddressType;
+use bitcoincore_rpc::{Client, RpcApi};
+use clap::Parser;
+use rust::{
+    addresses_by_label, balance_breakdown, build_report, connect, open_wallets, received_by_label,
+    recent_transactions, run_send, run_setup, verify_height_hash, Cli, Command, Config,
+    SendOutcome, TraderOutput, Wallets, MIN_SPENDABLE_BALANCE,
+};
+use std::env;
+
+// Connects to the configured node, or returns None (and says why) when there is no regtest
+// node to run against.
+fn regtest_node() -> Option<(Client, Config)> {
+    // One attempt is enough to tell whether a node is running.
+    env::set_var("BITCOIN_RPC_CONNECT_ATTEMPTS", "1");
+    let config = Config::from_env(Network::Regtest).expect("invalid RPC configuration");
+    match connect(&config).and_then(|rpc| Ok((rpc.get_blockchain_info()?.chain, rpc))) {
+        Ok((Network::Regtest, rpc)) => Some((rpc, config)),
+        Ok((chain, _)) => {
+            eprintln!("skipping: the configured node runs {chain}, not regtest");
+            None
+        }
+        Err(e) => {
+            eprintln!("skipping: no regtest node reachable ({e})");
+            None
+        }
+    }
+}
+
+// Parses a `send` command line for the given wallets.
+fn send_cli(miner: &str, trader: &str, extra: &[&str]) -> Cli {
+    let mut args = vec![
+        "rust",
+        "--miner-wallet",
+        miner,
+        "--trader-wallet",
+        trader,
+        "send",
+        "--send-amount",
+        "1",
+    ];
+    args.extend_from_slice(extra);
+    Cli::try_parse_from(args).expect("invalid test arguments")
+}
+
+// Opens the wallets `cli` names and mines the Miner a spendable balance, like `setup` does.
+// Returns the wallets and the address mined to.
+fn setup(rpc: &Client, config: &Config, cli: &Cli) -> (Wallets, Address) {
+    let wallets = open_wallets(rpc, &config.node(), &cli.global).unwrap();
+    let mining_address = run_setup(
+        &wallets,
+        Network::Regtest,
+        AddressType::Bech32m,
+        MIN_SPENDABLE_BALANCE,
+        false,
+    )
+    .unwrap();
+    (wallets, mining_address)
+}
+
+// Runs setup and then the send of `cli` like the binary does.
+fn setup_and_send(rpc: &Client, config: &Config, cli: &Cli) -> (Wallets, SendOutcome) {
+    let Some(Command::Send(send)) = &cli.command else {
+        panic!("not a send command");
+    };
+    let (wallets, mining_address) = setup(rpc, config, cli);
+    let sent = run_send(rpc, &wallets, Network::Regtest, send, &mining_address).unwrap();
+    (wallets, sent)
+}
+
+// Checks the report of the transaction a `send --send-amount 1` made.
+fn check_report(rpc: &Client, wallets: &Wallets, sent: &SendOutcome) {
+    let report = build_report(
+        wallets,
+        &sent.txid,
+        TraderOutput::Addresses(&sent.trader_addresses),
+        Network::Regtest,
+    )
+    .unwrap();
+
+    assert_eq!(report.txid, sent.txid);
+    assert_eq!(
+        report.trader_output_address,
+        sent.trader_addresses[0].to_string()
+    );
+    assert_eq!(report.trader_output_amount, Amount::ONE_BTC);
+    assert!(!report.miner_change_address.is_empty());
+    assert!(report.fee > Amount::ZERO);
+    assert_eq!(
+        report.miner_input_amount,
+        report.trader_output_amount + report.miner_change_amount + report.fee
+    );
+    assert!(verify_height_hash(rpc, report.block_height, &report.block_hash).unwrap());
+    assert_eq!(
+        sent.balance_delta.trader.to_sat(),
+        Amount::ONE_BTC.to_sat() as i64
+    );
+}
+
+#[test]
+fn send_to_address_report() {
+    let Some((rpc, config)) = regtest_node() else {
+        return;
+    };
+    let cli = send_cli("ItMiner", "ItTrader", &[]);
+    let (wallets, sent) = setup_and_send(&rpc, &config, &cli);
+    check_report(&rpc, &wallets, &sent);
+}
+
+#[test]
+fn manual_tx_report() {
+    let Some((rpc, config)) = regtest_node() else {
+        return;
+    };
+    let cli = send_cli("ItManualMiner", "ItManualTrader", &["--manual-tx"]);
+    let (wallets, sent) = setup_and_send(&rpc, &config, &cli);
+    check_report(&rpc, &wallets, &sent);
+}
+
+#[test]
+fn setup_mines_a_spendable_balance_to_a_labelled_address() {
+    let Some((rpc, config)) = regtest_node() else {
+        return;
+    };
+    let cli = send_cli("ItSetupMiner", "ItSetupTrader", &[]);
+    let (wallets, mining_address) = setup(&rpc, &config, &cli);
+    assert!(balance_breakdown(&wallets.miner).unwrap().trusted >= MIN_SPENDABLE_BALANCE);
+    assert!(
+        addresses_by_label(&wallets.miner, "Mining Reward", Network::Regtest)
+            .unwrap()
+            .contains(&mining_address)
+    );
+}
+
+#[test]
+fn transactions_lists_the_send() {
+    let Some((rpc, config)) = regtest_node() else {
+        return;
+    };
+    let cli = send_cli("ItListMiner", "ItListTrader", &[]);
+    let (wallets, sent) = setup_and_send(&rpc, &config, &cli);
+    assert!(recent_transactions(&wallets.trader, 10, 0)
+        .unwrap()
+        .iter()
+        .any(|tx| tx.info.txid == sent.txid));
+}
+
+#[test]
+fn received_subcommand_counts_the_send() {
+    let Some((rpc, config)) = regtest_node() else {
+        return;
+    };
+    let cli = send_cli("ItReceivedMiner", "ItReceivedTrader", &[]);
+    let (wallets, _) = setup_and_send(&rpc, &config, &cli);
+    assert!(received_by_label(&wallets.trader, "Received", 1).unwrap() >= Amount::ONE_BTC);
+}
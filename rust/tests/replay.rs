@@ -0,0 +1,398 @@
+// Runs the binary's flow against recorded RPC responses (`--replay`) instead of a node, so
+// these tests need no bitcoind.
+
+use bitcoincore_rpc::bitcoin::absolute::LockTime;
+use bitcoincore_rpc::bitcoin::consensus::encode::serialize_hex;
+use bitcoincore_rpc::bitcoin::hashes::Hash;
+use bitcoincore_rpc::bitcoin::transaction::Version;
+use bitcoincore_rpc::bitcoin::{
+    Address, Amount, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid,
+    WPubkeyHash, Witness,
+};
+use clap::Parser;
+use rust::{run, AppError, Cli};
+use serde_json::{json, Value};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const BEST_BLOCK: &str = "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206";
+const BLOCK_102: &str = "3d1b9c8f0a7e6d5c4b3a29180f7e6d5c4b3a29180f7e6d5c4b3a29180f7e6d5c";
+
+// One line of a trace as `--rpc-trace` writes it: `method` on the node endpoint answered with
+// `result`.
+fn traced(method: &str, result: Value) -> String {
+    traced_at("", method, json!([]), result)
+}
+
+// `method` with `params` on the endpoint at `path` (`/wallet/<name>`, or "" for the node)
+// answered with `result`.
+fn traced_at(path: &str, method: &str, params: Value, result: Value) -> String {
+    json!({
+        "time_ms": 0,
+        "target": format!("http://127.0.0.1:18443{path}"),
+        "method": method,
+        "params": params,
+        "id": 1,
+        "response": {"result": result, "error": null, "id": 1, "jsonrpc": "2.0"},
+    })
+    .to_string()
+}
+
+// Writes `lines` to a trace file of its own for test `name`.
+fn write_trace(name: &str, lines: &[String]) -> PathBuf {
+    let path = env::temp_dir().join(format!("rust-replay-{}-{name}.jsonl", std::process::id()));
+    fs::write(&path, lines.join("\n")).unwrap();
+    path
+}
+
+// Writes a trace of a regtest node at height 101 to a file of its own for test `name`. The node
+// is still in initial block download if `syncing`.
+fn regtest_trace(name: &str, syncing: bool) -> PathBuf {
+    write_trace(name, &regtest_node(syncing))
+}
+
+// What the node itself answers at height 101 (see regtest_trace).
+fn regtest_node(syncing: bool) -> Vec<String> {
+    vec![
+        // The client checks the node version before reading getblockchaininfo.
+        traced(
+            "getnetworkinfo",
+            json!({
+                "version": 270000,
+                "subversion": "/Satoshi:27.0.0/",
+                "protocolversion": 70016,
+                "localservices": "0000000000000c09",
+                "localrelay": true,
+                "timeoffset": 0,
+                "connections": 0,
+                "networkactive": true,
+                "networks": [],
+                "relayfee": 0.00001,
+                "incrementalfee": 0.00001,
+                "localaddresses": [],
+                "warnings": "",
+            }),
+        ),
+        traced(
+            "getblockchaininfo",
+            json!({
+                "chain": "regtest",
+                "blocks": 101,
+                "headers": if syncing { 150 } else { 101 },
+                "bestblockhash": BEST_BLOCK,
+                "difficulty": 4.6e-10,
+                "mediantime": 1700000000,
+                "verificationprogress": if syncing { 0.67 } else { 1.0 },
+                "initialblockdownload": syncing,
+                "chainwork": "00000000000000000000000000000000000000000000000000000000000000cc",
+                "size_on_disk": 30000,
+                "pruned": false,
+                "warnings": "",
+            }),
+        ),
+        traced(
+            "gettxoutsetinfo",
+            json!({
+                "height": 101,
+                "bestblock": BEST_BLOCK,
+                "txouts": 101,
+                "total_amount": 5050.0,
+            }),
+        ),
+    ]
+}
+
+#[test]
+fn info_runs_from_a_recorded_trace() {
+    let trace = regtest_trace("info", false);
+    let cli = Cli::parse_from(["rust", "--replay", trace.to_str().unwrap(), "--info"]);
+    let result = run(cli);
+    fs::remove_file(&trace).unwrap();
+    result.unwrap();
+}
+
+#[test]
+fn replayed_chain_is_checked_against_network() {
+    let trace = regtest_trace("network", false);
+    let cli = Cli::parse_from([
+        "rust",
+        "--replay",
+        trace.to_str().unwrap(),
+        "--network",
+        "signet",
+        "--info",
+    ]);
+    let result = run(cli);
+    fs::remove_file(&trace).unwrap();
+    assert!(matches!(result, Err(AppError::Config(message)) if message.contains("runs regtest")));
+}
+
+#[test]
+fn healthcheck_passes_once_the_node_is_synced() {
+    let trace = regtest_trace("healthy", false);
+    let cli = Cli::parse_from(["rust", "healthcheck", "--replay", trace.to_str().unwrap()]);
+    let result = run(cli);
+    fs::remove_file(&trace).unwrap();
+    result.unwrap();
+}
+
+#[test]
+fn healthcheck_fails_during_initial_block_download() {
+    let trace = regtest_trace("syncing", true);
+    let cli = Cli::parse_from(["rust", "healthcheck", "--replay", trace.to_str().unwrap()]);
+    let result = run(cli);
+    fs::remove_file(&trace).unwrap();
+    assert!(
+        matches!(&result, Err(AppError::NodeNotReady(message)) if message.contains("block 101 of 150")),
+        "{result:?}"
+    );
+}
+
+// The Miner pays the Trader 20 BTC out of a 50 BTC output with a 1000 sat fee, in block 102.
+struct Payment {
+    funding: Transaction,
+    spend: Transaction,
+    miner_input: Address,
+    trader: Address,
+    change: Address,
+}
+
+fn address(n: u8) -> Address {
+    let script = ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([n; 20]));
+    Address::from_script(&script, Network::Regtest).unwrap()
+}
+
+fn tx(input: OutPoint, outputs: &[(&Address, u64)]) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: input,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        }],
+        output: outputs
+            .iter()
+            .map(|(address, sats)| TxOut {
+                value: Amount::from_sat(*sats),
+                script_pubkey: address.script_pubkey(),
+            })
+            .collect(),
+    }
+}
+
+// `getaddressinfo` for `address` on the wallet at `path`, which owns it if `mine`.
+fn address_info(path: &str, address: &Address, mine: bool) -> String {
+    traced_at(
+        path,
+        "getaddressinfo",
+        json!([address]),
+        json!({
+            "address": address,
+            "scriptPubKey": address.script_pubkey().to_hex_string(),
+            "ismine": mine,
+            "iswatchonly": false,
+            "labels": [],
+        }),
+    )
+}
+
+// What `decoderawtransaction` answers for `tx`.
+fn decoded(tx: &Transaction) -> Value {
+    let vin: Vec<Value> = tx
+        .input
+        .iter()
+        .map(|input| {
+            json!({
+                "txid": input.previous_output.txid,
+                "vout": input.previous_output.vout,
+                "scriptSig": {"asm": "", "hex": ""},
+                "sequence": input.sequence.0,
+            })
+        })
+        .collect();
+    let vout: Vec<Value> = tx
+        .output
+        .iter()
+        .enumerate()
+        .map(|(n, output)| {
+            let address = Address::from_script(&output.script_pubkey, Network::Regtest).unwrap();
+            json!({
+                "value": output.value.to_btc(),
+                "n": n,
+                "scriptPubKey": {
+                    "asm": "",
+                    "hex": output.script_pubkey.to_hex_string(),
+                    "type": "witness_v0_keyhash",
+                    "address": address,
+                },
+            })
+        })
+        .collect();
+    json!({
+        "txid": tx.txid(),
+        "hash": tx.wtxid(),
+        "size": tx.total_size(),
+        "vsize": tx.vsize(),
+        "weight": tx.weight().to_wu(),
+        "version": tx.version.0,
+        "locktime": tx.lock_time.to_consensus_u32(),
+        "vin": vin,
+        "vout": vout,
+    })
+}
+
+fn payment() -> Payment {
+    let (miner_input, trader, change) = (address(1), address(2), address(3));
+    let funding = tx(
+        OutPoint::new(Txid::from_byte_array([9; 32]), 0),
+        &[(&miner_input, 5_000_000_000)],
+    );
+    let spend = tx(
+        OutPoint::new(funding.txid(), 0),
+        &[(&trader, 2_000_000_000), (&change, 2_999_999_000)],
+    );
+    Payment {
+        funding,
+        spend,
+        miner_input,
+        trader,
+        change,
+    }
+}
+
+#[test]
+fn report_runs_from_a_recorded_trace() {
+    let p = payment();
+    let (txid, funding_txid) = (p.spend.txid(), p.funding.txid());
+    let mut lines = regtest_node(false);
+    lines.extend([
+        traced("listwallets", json!(["Miner", "Trader"])),
+        traced_at(
+            "/wallet/Miner",
+            "gettransaction",
+            json!([txid]),
+            json!({
+                "amount": -20.0,
+                "fee": -0.00001,
+                "confirmations": 1,
+                "blockhash": BLOCK_102,
+                "blockheight": 102,
+                "blockindex": 1,
+                "blocktime": 1700000600,
+                "txid": txid,
+                "wtxid": p.spend.wtxid(),
+                "walletconflicts": [],
+                "time": 1700000000,
+                "timereceived": 1700000000,
+                "bip125-replaceable": "no",
+                "details": [],
+                "hex": serialize_hex(&p.spend),
+            }),
+        ),
+        traced_at(
+            "/wallet/Miner",
+            "getblock",
+            json!([BLOCK_102, 1]),
+            json!({
+                "hash": BLOCK_102,
+                "confirmations": 1,
+                "size": 500,
+                "strippedsize": 500,
+                "weight": 2000,
+                "height": 102,
+                "version": 536870912,
+                "versionHex": "20000000",
+                "merkleroot": BEST_BLOCK,
+                "tx": [txid],
+                "time": 1700000600,
+                "mediantime": 1700000000,
+                "nonce": 0,
+                "bits": "207fffff",
+                "difficulty": 4.6e-10,
+                "chainwork": "00000000000000000000000000000000000000000000000000000000000000ce",
+                "nTx": 1,
+                "previousblockhash": BEST_BLOCK,
+            }),
+        ),
+        traced_at(
+            "/wallet/Miner",
+            "getrawtransaction",
+            json!([txid, false, BLOCK_102]),
+            json!(serialize_hex(&p.spend)),
+        ),
+        traced_at(
+            "/wallet/Miner",
+            "decoderawtransaction",
+            json!([serialize_hex(&p.spend)]),
+            decoded(&p.spend),
+        ),
+        // The output is spent, so the wallet's copy of the funding transaction is used.
+        traced_at(
+            "/wallet/Miner",
+            "gettxout",
+            json!([funding_txid, 0, true]),
+            Value::Null,
+        ),
+        traced_at(
+            "/wallet/Miner",
+            "gettransaction",
+            json!([funding_txid]),
+            json!({
+                "amount": 50.0,
+                "confirmations": 2,
+                "blockhash": BEST_BLOCK,
+                "blockheight": 101,
+                "blockindex": 0,
+                "blocktime": 1700000000,
+                "txid": funding_txid,
+                "wtxid": p.funding.wtxid(),
+                "walletconflicts": [],
+                "time": 1700000000,
+                "timereceived": 1700000000,
+                "bip125-replaceable": "no",
+                "details": [],
+                "hex": serialize_hex(&p.funding),
+            }),
+        ),
+        // Without --trader-address, the Trader wallet picks out its own output.
+        address_info("/wallet/Trader", &p.trader, true),
+        address_info("/wallet/Trader", &p.change, false),
+        address_info("/wallet/Miner", &p.change, true),
+    ]);
+    let trace = write_trace("report", &lines);
+    let out = env::temp_dir().join(format!("rust-replay-{}-out.txt", std::process::id()));
+    let cli = Cli::parse_from([
+        "rust",
+        "report",
+        "--txid",
+        &p.spend.txid().to_string(),
+        "--out",
+        out.to_str().unwrap(),
+        "--format",
+        "text",
+        "--no-summary",
+        "--replay",
+        trace.to_str().unwrap(),
+    ]);
+    let result = run(cli);
+    fs::remove_file(&trace).unwrap();
+    result.unwrap();
+    let written = fs::read_to_string(&out).unwrap();
+    fs::remove_file(&out).unwrap();
+    let expected = [
+        txid.to_string(),
+        p.miner_input.to_string(),
+        "50.00000000".to_owned(),
+        p.trader.to_string(),
+        "20.00000000".to_owned(),
+        p.change.to_string(),
+        "29.99999000".to_owned(),
+        "0.00001000".to_owned(),
+        "102".to_owned(),
+        BLOCK_102.to_owned(),
+    ];
+    assert_eq!(written.lines().collect::<Vec<_>>(), expected);
+}